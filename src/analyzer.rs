@@ -1,26 +1,146 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::rc::Rc;
 
+use crate::diagnostic::{Diagnostic, Diagnostics, Severity};
+use crate::inference::{Inference, Signature, TypeTable};
+use crate::lint;
+use crate::lint::{Level, Lint, LintLevels, LINTS};
 use crate::logger::Logger;
+use crate::span::Span;
+use crate::suggest;
 use crate::symbols::{Symbol, Symbols};
-use crate::tree::{ExprNode, FuncNode, ProgramNode, StmtNode};
+use crate::tree::{BlockNode, ExprNode, FuncNode, ProgramNode, Slot, StmtNode};
+use crate::types::Type;
 use crate::value::Value;
 
 pub struct Analyzer {
     program: Rc<ProgramNode>,
+    types: RefCell<TypeTable>,
+    diagnostics: Rc<Diagnostics>,
+    lints: LintLevels,
+    errors: Cell<usize>,
+    // statement being type checked, stands in for expressions made of literals only
+    statement: Cell<Span>,
 }
 
 impl Analyzer {
-    pub fn new(program: Rc<ProgramNode>) -> Analyzer {
-        Analyzer { program }
+    pub fn new(program: Rc<ProgramNode>, diagnostics: Rc<Diagnostics>) -> Analyzer {
+        Analyzer {
+            program,
+            types: RefCell::new(TypeTable::default()),
+            diagnostics,
+            lints: LintLevels::new(),
+            errors: Cell::new(0),
+            statement: Cell::new(Span::default()),
+        }
+    }
+
+    pub fn set_lint_levels(&mut self, lints: LintLevels) {
+        self.lints = lints;
     }
 
-    pub fn analyze(&self) {
+    /// Signature of every function as inferred by the last `analyze` run.
+    pub fn signatures(&self) -> Vec<String> {
+        let types = self.types.borrow();
+        self.program.func_nodes.iter()
+            .filter_map(|rc_func| types.signatures.get(&rc_func.name).map(|signature| signature.format(rc_func)))
+            .collect()
+    }
+
+    /// Types found by the last `analyze` run.
+    pub fn into_types(self) -> TypeTable {
+        self.types.into_inner()
+    }
+
+    pub fn analyze(&self) -> Result<(), String> {
         Logger::info("Analyze.");
+        self.check_attributes_program();
         self.collect_symbols_program();
         self.reference_symbols_program();
+        *self.types.borrow_mut() = Inference::infer(&self.program);
+        self.check_types_program();
+        self.check_assigned_program();
         self.check_unused_variables_program();
+        self.check_dead_code_program();
+        self.check_missing_returns_program();
+        self.check_unused_functions_program();
+        self.check_shadowing_program();
+        self.mark_tail_calls_program();
+        self.resolve_slots_program();
+
+        match self.errors.get() {
+            0 => Ok(()),
+            1 => Err("Analysis failed due to 1 previous error.".to_string()),
+            n => Err(format!("Analysis failed due to {n} previous errors.")),
+        }
+    }
+
+    fn report(&self, diagnostic: Diagnostic) {
+        if diagnostic.severity == Severity::Error {
+            self.errors.set(self.errors.get() + 1);
+        }
+        self.diagnostics.report(diagnostic);
+    }
+
+    // reports the finding of a lint at the level set for the function, or globally if there is none
+    fn lint(&self, lint: &Lint, func: Option<&str>, diagnostic: Diagnostic) {
+        let attribute = func
+            .and_then(|func| self.program.func_nodes.iter().find(|rc_func| rc_func.name == func))
+            .and_then(|rc_func| Self::lint_attribute(rc_func, lint));
+        let level = match attribute {
+            Some((level, _)) => level,
+            None => self.lints.get(lint),
+        };
+
+        match level {
+            Level::Allow => {}
+            Level::Warn => self.report(diagnostic),
+            Level::Deny => {
+                let mut diagnostic = diagnostic;
+                diagnostic.severity = Severity::Error;
+                let diagnostic = match attribute {
+                    Some((_, span)) => diagnostic.with_secondary(span, "the lint level is defined here"),
+                    None if self.lints.is_set(lint) => diagnostic.with_note(format!("'-D {}' is given on the command line", lint.name)),
+                    None => diagnostic,
+                };
+                self.report(diagnostic);
+            }
+        }
+    }
+
+    // the last attribute of the function that names the lint
+    fn lint_attribute(rc_func: &FuncNode, lint: &Lint) -> Option<(Level, Span)> {
+        rc_func.attributes.iter().rev()
+            .filter(|attribute| attribute.args.iter().any(|(arg, _)| lint::find(arg).is_some_and(|found| found.name == lint.name)))
+            .find_map(|attribute| Level::from_attribute(&attribute.name).map(|level| (level, attribute.span)))
+    }
+
+    fn span_of(&self, expr: &ExprNode) -> Span {
+        expr.span().unwrap_or(self.statement.get())
+    }
+
+    fn check_attributes_program(&self) {
+        for rc_func in &self.program.func_nodes {
+            for attribute in &rc_func.attributes {
+                if Level::from_attribute(&attribute.name).is_none() {
+                    self.report(
+                        Diagnostic::error("E0107", format!("unknown attribute '{name}'", name = attribute.name))
+                            .with_primary(attribute.span, "expected allow, warn or deny"));
+                    continue;
+                }
+                for (arg, span) in &attribute.args {
+                    if lint::find(arg).is_none() {
+                        let known: Vec<String> = LINTS.iter().map(|lint| lint.name.replace('-', "_")).collect();
+                        self.report(
+                            Diagnostic::error("E0108", format!("unknown lint '{arg}'"))
+                                .with_primary(*span, "not a lint")
+                                .with_note(format!("known lints are {}", known.join(", "))));
+                    }
+                }
+            }
+        }
     }
 
     fn collect_symbols_program(&self) {
@@ -36,32 +156,35 @@ impl Analyzer {
             let symbol = Symbol::new(
                 name.clone(),
                 Value::Func(rc_func.clone(), num_params),
-                num_params);
-            match symbols.map.insert(name.clone(), symbol) {
-                None => {
-                    /* all good */
-                    Self::collect_symbols_block_function(
-                        rc_func.clone(),
-                        rc_symbols.clone(),
-                    );
-                }
-                Some(_) => { panic!("Duplicate identifier '{:}'!", name) }
+                num_params,
+                rc_func.span);
+            match symbols.map.get(name) {
+                None => { symbols.map.insert(name.clone(), symbol); }
+                Some(previous) => self.report(Self::duplicate(name, rc_func.span, previous.span)),
             }
+            // duplicates are still checked, against the first definition
+            self.collect_symbols_block_function(rc_func.clone(), rc_symbols.clone());
         }
 
         // collect let node symbols
         for rc_let in &self.program.let_nodes {
             let name = &rc_let.name;
-            let symbol = Symbol::new(name.clone(), Value::Nil, 0);
+            let symbol = Symbol::new(name.clone(), rc_let.value.clone(), 0, rc_let.span);
             Logger::debug(&format!("Collecting symbol '{:}'.", name));
-            match symbols.map.insert(name.clone(), symbol) {
-                None => { /* all good */ }
-                Some(_) => { panic!("Duplicate identifier '{:}'!", name) }
+            match symbols.map.get(name) {
+                None => { symbols.map.insert(name.clone(), symbol); }
+                Some(previous) => self.report(Self::duplicate(name, rc_let.span, previous.span)),
             }
         }
     }
 
-    fn collect_symbols_block_function(rc_func: Rc<FuncNode>, rc_symbols_global: Rc<RefCell<Symbols>>) {
+    fn duplicate(name: &str, span: Span, previous: Span) -> Diagnostic {
+        Diagnostic::error("E0101", format!("the name '{name}' is defined multiple times"))
+            .with_primary(span, &format!("'{name}' redefined here"))
+            .with_secondary(previous, &format!("previous definition of '{name}' here"))
+    }
+
+    fn collect_symbols_block_function(&self, rc_func: Rc<FuncNode>, rc_symbols_global: Rc<RefCell<Symbols>>) {
 
         // get function node symbol table
         let rc_symbols = rc_func.block_node.symbols.clone();
@@ -73,135 +196,1018 @@ impl Analyzer {
         // collect parameter symbols
         for param in &rc_func.parameters {
             let name = &param.name;
-            let symbol = Symbol::new(name.clone(), Value::Nil, 0);
-            match symbols.map.insert(name.clone(), symbol) {
-                None => { /* all good */ }
-                Some(_) => { panic!("Duplicate parameter name '{:}' in function {:}!", name, rc_func.name) }
+            let symbol = Symbol::new(name.clone(), Value::Nil, 0, param.span);
+            match symbols.map.get(name) {
+                None => { symbols.map.insert(name.clone(), symbol); }
+                Some(previous) => self.report(
+                    Diagnostic::error("E0102", format!("identifier '{name}' is bound more than once in the parameter list of function '{func}'", func = rc_func.name))
+                        .with_primary(param.span, "used as parameter more than once")
+                        .with_secondary(previous.span, "first bound here")),
             }
         }
+        drop(symbols);
 
         // collect let node symbols
-        for rc_stmt in &rc_func.block_node.statements {
-            if let StmtNode::Let(letNode) = rc_stmt.deref() {
-                let name = &letNode.name;
-                let symbol = Symbol::new(name.clone(), Value::Nil, 0);
-                match symbols.map.insert(name.clone(), symbol) {
-                    None => { /* all good */ }
-                    Some(_) => { panic!("Duplicate parameter name '{:}' in function {:}!", name, rc_func.name) }
+        self.collect_symbols_block(&rc_func.block_node, &rc_func);
+    }
+
+    fn collect_symbols_block(&self, block: &BlockNode, rc_func: &FuncNode) {
+        let func = &rc_func.name;
+        for rc_stmt in &block.statements {
+            match rc_stmt.deref() {
+                StmtNode::Let(letNode) => {
+                    let name = &letNode.name;
+                    let symbol = Symbol::new(name.clone(), Value::Nil, 0, letNode.span);
+                    let previous = block.symbols.borrow().map.get(name).map(|previous| previous.span);
+                    // parameters share the table of the function body
+                    let is_param = |previous: Span| rc_func.parameters.iter().any(|param| param.span == previous);
+                    match previous {
+                        None => { block.symbols.borrow_mut().map.insert(name.clone(), symbol); }
+                        Some(previous) if is_param(previous) => self.report(
+                            Diagnostic::error("E0109", format!("variable '{name}' redeclares a parameter of function '{func}'"))
+                                .with_primary(letNode.span, "declared again here")
+                                .with_secondary(previous, &format!("parameter '{name}' declared here"))
+                                .with_help(format!("assign to '{name}' instead, or give the variable another name"))),
+                        Some(previous) => self.report(
+                            Diagnostic::error("E0102", format!("variable '{name}' is declared more than once in the same block of function '{func}'"))
+                                .with_primary(letNode.span, "declared again here")
+                                .with_secondary(previous, "first declared here")),
+                    }
+                }
+                StmtNode::While(whileNode) => {
+                    self.collect_symbols_nested_block(&whileNode.body, block, rc_func);
+                }
+                StmtNode::IfElse(ifNode) => {
+                    self.collect_symbols_nested_block(&ifNode.ifBody, block, rc_func);
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        self.collect_symbols_nested_block(elseBody, block, rc_func);
+                    }
                 }
+                _ => {}
             }
         }
     }
 
+    fn collect_symbols_nested_block(&self, nested: &BlockNode, enclosing: &BlockNode, rc_func: &FuncNode) {
+        // link to enclosing symbols table
+        nested.symbols.borrow_mut().parent = Some(enclosing.symbols.clone());
+        self.collect_symbols_block(nested, rc_func);
+    }
+
     fn reference_symbols_program(&self) {
         for rc_func in &self.program.func_nodes {
-            self.reference_symbols_block_function(rc_func.clone());
+            self.reference_symbols_block(&rc_func.block_node, &rc_func.name);
         }
     }
 
-    fn reference_symbols_block_function(&self, rc_func: Rc<FuncNode>) {
-        let rc_symbols = rc_func.block_node.symbols.clone();
-        let mut symbols = rc_symbols.borrow_mut();
+    fn reference_symbols_block(&self, block: &BlockNode, func: &String) {
+        let symbols = &block.symbols;
 
-        for rc_stmt in &rc_func.block_node.statements {
+        for rc_stmt in &block.statements {
             match rc_stmt.deref() {
                 StmtNode::Let(letNode) => {
-                    if !symbols.map.contains_key(&letNode.name) {
-                        panic!("Variable '{:}' used before declaration in function {:}!", letNode.name, rc_func.name);
+                    if !symbols.borrow().map.contains_key(&letNode.name) {
+                        self.report(Self::undeclared(&letNode.name, letNode.span, func, symbols));
                     }
                 }
                 StmtNode::Assign(assignNode) => {
-                    if !symbols.map.contains_key(&assignNode.name) {
-                        panic!("Variable '{:}' used before declaration in function {:}!", assignNode.name, rc_func.name);
-                    }
-                    if let Some(symbol) = symbols.map.get_mut(&assignNode.name) {
-                        symbol.is_used = true;
+                    if !symbols.borrow().is_declared(&assignNode.name) {
+                        self.report(Self::undeclared(&assignNode.name, assignNode.span, func, symbols));
                     }
-                    self.reference_symbols_expression(&assignNode.expr, &mut symbols);
+                    symbols.borrow_mut().mark_used(&assignNode.name);
+                    self.reference_symbols_expression(&assignNode.expr, symbols, func);
                 }
                 StmtNode::IfElse(ifNode) => {
-                    self.reference_symbols_expression(&ifNode.condition, &mut symbols);
+                    self.reference_symbols_expression(&ifNode.condition, symbols, func);
+                    self.reference_symbols_block(&ifNode.ifBody, func);
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        self.reference_symbols_block(elseBody, func);
+                    }
                 }
                 StmtNode::Return(returnNode) => {
-                    self.reference_symbols_expression(&returnNode.expr, &mut symbols);
+                    self.reference_symbols_expression(&returnNode.expr, symbols, func);
+                }
+                StmtNode::Print(printNode) => {
+                    self.reference_symbols_expression(&printNode.expr, symbols, func);
                 }
                 StmtNode::While(whileNode) => {
-                    self.reference_symbols_expression(&whileNode.condition, &mut symbols);
+                    self.reference_symbols_expression(&whileNode.condition, symbols, func);
+                    self.reference_symbols_block(&whileNode.body, func);
                 }
-                _ => {}
             }
         }
     }
 
-    fn reference_symbols_expression(&self, expr: &ExprNode, symbols: &mut Symbols) {
+    fn reference_symbols_expression(&self, expr: &ExprNode, symbols: &RefCell<Symbols>, func: &String) {
         match expr {
-            ExprNode::Var(varNode) => {
-                if let Some(symbol) = symbols.map.get_mut(varNode) {
-                    symbol.is_used = true;
+            ExprNode::Var(varNode, span, _) => {
+                if !symbols.borrow().is_declared(varNode) {
+                    self.report(Self::undeclared(varNode, *span, func, symbols));
                 }
+                symbols.borrow_mut().mark_used(varNode);
             }
-            ExprNode::Call(callNode, args) => {
-                if let Some(symbol) = symbols.map.get_mut(callNode) {
-                    symbol.is_used = true;
-                }
+            ExprNode::Call(callNode, args, span, _) => {
+                self.check_call(callNode, args.len(), span, func);
+                symbols.borrow_mut().mark_used(callNode);
                 for expr in args {
-                    self.reference_symbols_expression(expr, symbols);
+                    self.reference_symbols_expression(expr, symbols, func);
                 }
             }
             ExprNode::Add(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
             }
             ExprNode::Sub(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
             }
             ExprNode::Mul(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
+            }
+            ExprNode::Div(expr1, expr2) => {
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
+            }
+            ExprNode::And(expr1, expr2) => {
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
+            }
+            ExprNode::Or(expr1, expr2) => {
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
+            }
+            ExprNode::Not(expr) => {
+                self.reference_symbols_expression(expr, symbols, func);
             }
             ExprNode::EqualTo(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
             }
             ExprNode::NotEqualTo(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
             }
             ExprNode::GreaterThan(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
             }
             ExprNode::LessThan(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
             }
             ExprNode::GreaterThanEq(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
             }
             ExprNode::LessThanEq(expr1, expr2) => {
-                self.reference_symbols_expression(expr1, symbols);
-                self.reference_symbols_expression(expr2, symbols);
+                self.reference_symbols_expression(expr1, symbols, func);
+                self.reference_symbols_expression(expr2, symbols, func);
+            }
+            ExprNode::Val(_) | ExprNode::String(_) => {}
+        }
+    }
+
+    fn undeclared(name: &str, span: Span, func: &str, symbols: &RefCell<Symbols>) -> Diagnostic {
+        let diagnostic = Diagnostic::error("E0103", format!("cannot find variable '{name}' in this scope"))
+            .with_primary(span, &format!("not declared in function '{func}' or globally"));
+
+        let variables: Vec<Symbol> = symbols.borrow().visible().into_iter()
+            .filter(|symbol| !matches!(symbol.value, Value::Func(_, _)))
+            .collect();
+        Self::suggest(diagnostic, name, &variables, "variable")
+    }
+
+    // points at the symbol the unknown name was probably meant to be, or at a keyword
+    fn suggest(diagnostic: Diagnostic, name: &str, candidates: &[Symbol], kind: &str) -> Diagnostic {
+        let similar = suggest::closest(name, candidates.iter().map(|symbol| symbol.name.as_str()));
+        if let Some(symbol) = similar.and_then(|similar| candidates.iter().find(|symbol| symbol.name == similar)) {
+            return diagnostic
+                .with_secondary(symbol.span, &format!("a {kind} with a similar name is declared here"))
+                .with_help(format!("did you mean '{similar}'?", similar = symbol.name));
+        }
+        match suggest::keyword(name) {
+            Some(keyword) => diagnostic.with_help(format!("did you mean the keyword '{keyword}'?")),
+            None => diagnostic,
+        }
+    }
+
+    // calls resolve against the global function table, like Evaluator does
+    fn check_call(&self, name: &String, argc: usize, span: &Span, func: &String) {
+        let symbols = self.program.symbols.borrow();
+        let diagnostic = match symbols.map.get(name) {
+            Some(Symbol { value: Value::Func(_, _), signature, .. }) if *signature == argc => return,
+            Some(Symbol { value: Value::Func(_, _), signature, span: declared, .. }) => {
+                Diagnostic::error("E0104", format!("function '{name}' takes {signature} argument(s) but {argc} were supplied"))
+                    .with_primary(*span, &format!("called with {argc} argument(s)"))
+                    .with_secondary(*declared, &format!("'{name}' defined here"))
+            }
+            Some(Symbol { span: declared, .. }) => {
+                Diagnostic::error("E0105", format!("'{name}' is not a function"))
+                    .with_primary(*span, "called here")
+                    .with_secondary(*declared, &format!("'{name}' is a global variable"))
+            }
+            None => {
+                let functions: Vec<Symbol> = symbols.map.values()
+                    .filter(|symbol| matches!(symbol.value, Value::Func(_, _)))
+                    .cloned()
+                    .collect();
+                let diagnostic = Diagnostic::error("E0106", format!("cannot find function '{name}' in this scope"))
+                    .with_primary(*span, &format!("called in function '{func}'"));
+                Self::suggest(diagnostic, name, &functions, "function")
+            }
+        };
+        drop(symbols);
+        self.report(diagnostic);
+    }
+
+    fn check_assigned_program(&self) {
+        let assigned_by = Rc::new(self.globals_assigned_by_calls());
+        for rc_func in &self.program.func_nodes {
+            // a global declared without a value is unassigned when main starts, any other function may
+            // run after an assignment, so there it counts as assigned if some function assigns it
+            let globals = self.program.let_nodes.iter()
+                .map(|rc_let| {
+                    let assigned = rc_let.value != Value::Nil
+                        || rc_func.name != "main" && self.program.func_nodes.iter().any(|rc_other| rc_other.block_node.assigns(&rc_let.name));
+                    (rc_let.name.clone(), assigned)
+                })
+                .collect();
+            // parameters are assigned by the caller
+            let params = rc_func.parameters.iter().map(|param| (param.name.clone(), true)).collect();
+            let mut state = Assigned { scopes: vec![globals, params], reachable: true, assigned_by: assigned_by.clone() };
+            self.check_assigned_block(&rc_func.block_node, &rc_func.name, &mut state);
+        }
+    }
+
+    // globals declared without a value that a call of each function may assign, in its body or
+    // in the functions it calls
+    fn globals_assigned_by_calls(&self) -> HashMap<String, Vec<String>> {
+        let funcs: HashMap<&String, &Rc<FuncNode>> = self.program.func_nodes.iter()
+            .map(|rc_func| (&rc_func.name, rc_func))
+            .collect();
+        let globals: Vec<&String> = self.program.let_nodes.iter()
+            .filter(|rc_let| rc_let.value == Value::Nil)
+            .map(|rc_let| &rc_let.name)
+            .collect();
+
+        let mut assigned_by = HashMap::new();
+        for rc_func in &self.program.func_nodes {
+            let mut reached = vec![rc_func.name.clone()];
+            let mut pending = vec![rc_func];
+            while let Some(rc_reached) = pending.pop() {
+                let mut callees = vec![];
+                rc_reached.block_node.collect_calls(&mut callees);
+                for callee in callees {
+                    if let Some(rc_callee) = funcs.get(&callee) {
+                        if !reached.contains(&callee) {
+                            reached.push(callee);
+                            pending.push(rc_callee);
+                        }
+                    }
+                }
+            }
+            let assigned = globals.iter()
+                .filter(|name| reached.iter().any(|reached| funcs[reached].block_node.assigns(name)))
+                .map(|name| name.to_string())
+                .collect();
+            assigned_by.insert(rc_func.name.clone(), assigned);
+        }
+        assigned_by
+    }
+
+    fn check_assigned_block(&self, block: &BlockNode, func: &String, state: &mut Assigned) {
+        state.scopes.push(HashMap::new());
+
+        for rc_stmt in &block.statements {
+            match rc_stmt.deref() {
+                StmtNode::Let(letNode) => {
+                    state.declare(&letNode.name, letNode.value != Value::Nil);
+                }
+                StmtNode::Assign(assignNode) => {
+                    self.check_assigned_expression(&assignNode.expr, func, state);
+                    state.assign(&assignNode.name);
+                }
+                StmtNode::Return(returnNode) => {
+                    self.check_assigned_expression(&returnNode.expr, func, state);
+                    state.reachable = false;
+                }
+                StmtNode::Print(printNode) => {
+                    self.check_assigned_expression(&printNode.expr, func, state);
+                }
+                StmtNode::While(whileNode) => {
+                    self.check_assigned_expression(&whileNode.condition, func, state);
+                    // the body may run zero times, so its assignments are not definite afterwards
+                    match whileNode.condition.deref() {
+                        ExprNode::Val(Value::Bool(false)) => {}
+                        ExprNode::Val(Value::Bool(true)) => {
+                            self.check_assigned_block(&whileNode.body, func, &mut state.clone());
+                            state.reachable = false;
+                        }
+                        _ => self.check_assigned_block(&whileNode.body, func, &mut state.clone()),
+                    }
+                }
+                StmtNode::IfElse(ifNode) => {
+                    self.check_assigned_expression(&ifNode.condition, func, state);
+                    let mut then_state = state.clone();
+                    let mut else_state = state.clone();
+                    match ifNode.condition.deref() {
+                        ExprNode::Val(Value::Bool(true)) => else_state.reachable = false,
+                        ExprNode::Val(Value::Bool(false)) => then_state.reachable = false,
+                        _ => {}
+                    }
+                    self.check_assigned_block(&ifNode.ifBody, func, &mut then_state);
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        self.check_assigned_block(elseBody, func, &mut else_state);
+                    }
+                    *state = then_state.join(else_state);
+                }
             }
-            _ => {}
+        }
+
+        state.scopes.pop();
+    }
+
+    fn check_assigned_expression(&self, expr: &ExprNode, func: &String, state: &mut Assigned) {
+        match expr {
+            ExprNode::Var(name, span, _) => {
+                if state.reachable && !state.is_assigned(name) {
+                    self.report(
+                        Diagnostic::error("E0302", format!("variable '{name}' may be read before it is assigned"))
+                            .with_primary(*span, &format!("read here in function '{func}'"))
+                            .with_note("a variable declared without a value must be assigned on every path before it is read".to_string()));
+                    // report each variable only once per path
+                    state.assign(name);
+                }
+            }
+            ExprNode::Call(name, args, ..) => {
+                for arg in args {
+                    self.check_assigned_expression(arg, func, state);
+                }
+                state.assign_globals_of(name);
+            }
+            ExprNode::Add(a, b) | ExprNode::Sub(a, b) | ExprNode::Mul(a, b) | ExprNode::Div(a, b)
+            | ExprNode::LessThan(a, b) | ExprNode::GreaterThan(a, b) | ExprNode::EqualTo(a, b)
+            | ExprNode::LessThanEq(a, b) | ExprNode::GreaterThanEq(a, b) | ExprNode::NotEqualTo(a, b)
+            | ExprNode::And(a, b) | ExprNode::Or(a, b) => {
+                self.check_assigned_expression(a, func, state);
+                self.check_assigned_expression(b, func, state);
+            }
+            ExprNode::Not(a) => self.check_assigned_expression(a, func, state),
+            ExprNode::Val(_) | ExprNode::String(_) => {}
         }
     }
 
     fn check_unused_variables_program(&self) {
+        let symbols = self.program.symbols.borrow();
+        for rc_let in &self.program.let_nodes {
+            if let Some(symbol) = symbols.map.get(&rc_let.name) {
+                if !symbol.is_used {
+                    self.lint(&lint::UNUSED_VARIABLE, None,
+                        Diagnostic::warning(lint::UNUSED_VARIABLE.name, format!("unused global variable '{name}'", name = rc_let.name))
+                            .with_primary(symbol.span, "declared here but never used"));
+                }
+            }
+        }
+
         for rc_func in &self.program.func_nodes {
-            self.check_unused_variables_block_function(rc_func.clone());
+            self.check_unused_variables_block(&rc_func.block_node, &rc_func.name);
         }
     }
 
-    fn check_unused_variables_block_function(&self, rc_func: Rc<FuncNode>) {
-        let rc_symbols = rc_func.block_node.symbols.clone();
-        let symbols = rc_symbols.borrow();
+    fn check_unused_variables_block(&self, block: &BlockNode, func: &String) {
+        let symbols = block.symbols.borrow();
+        let mut names: Vec<&String> = symbols.map.keys().collect();
+        names.sort();
 
-        for (name, symbol) in symbols.map.iter() {
+        for name in names {
+            let symbol = &symbols.map[name];
             if !symbol.is_used {
-                Logger::warn(&format!("Warning: Variable '{name:}' declared but not used in function {func:}!", name = name, func = rc_func.name));
+                self.lint(&lint::UNUSED_VARIABLE, Some(func),
+                    Diagnostic::warning(lint::UNUSED_VARIABLE.name, format!("unused variable '{name}' in function '{func}'"))
+                        .with_primary(symbol.span, "declared here but never used"));
+            }
+        }
+
+        for rc_stmt in &block.statements {
+            match rc_stmt.deref() {
+                StmtNode::While(whileNode) => {
+                    self.check_unused_variables_block(&whileNode.body, func);
+                }
+                StmtNode::IfElse(ifNode) => {
+                    self.check_unused_variables_block(&ifNode.ifBody, func);
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        self.check_unused_variables_block(elseBody, func);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_dead_code_program(&self) {
+        for rc_func in &self.program.func_nodes {
+            self.check_dead_code_block(&rc_func.block_node, &rc_func.name);
+        }
+    }
+
+    fn check_dead_code_block(&self, block: &BlockNode, func: &String) {
+        for (i, rc_stmt) in block.statements.iter().enumerate() {
+            match rc_stmt.deref() {
+                StmtNode::Return(returnNode) => {
+                    // only report the first dead statement of the block
+                    if let Some(next) = block.statements.get(i + 1) {
+                        self.lint(&lint::UNREACHABLE_CODE, Some(func),
+                            Diagnostic::warning(lint::UNREACHABLE_CODE.name, format!("unreachable statement in function '{func}'"))
+                                .with_primary(next.span(), "unreachable statement")
+                                .with_secondary(returnNode.span, "any code following this return is unreachable"));
+                    }
+                    return;
+                }
+                StmtNode::While(whileNode) => {
+                    match whileNode.condition.deref() {
+                        ExprNode::Val(Value::Bool(false)) => {
+                            self.lint(&lint::UNREACHABLE_CODE, Some(func),
+                                Diagnostic::warning(lint::UNREACHABLE_CODE.name, format!("unreachable loop body in function '{func}'"))
+                                    .with_primary(whileNode.span, "condition is always false"));
+                        }
+                        _ => self.check_dead_code_block(&whileNode.body, func),
+                    }
+                }
+                StmtNode::IfElse(ifNode) => {
+                    match ifNode.condition.deref() {
+                        ExprNode::Val(Value::Bool(true)) => {
+                            self.check_dead_code_block(&ifNode.ifBody, func);
+                            if ifNode.elseBody.is_some() {
+                                self.lint(&lint::UNREACHABLE_CODE, Some(func),
+                                    Diagnostic::warning(lint::UNREACHABLE_CODE.name, format!("unreachable else branch in function '{func}'"))
+                                        .with_primary(ifNode.span, "condition is always true"));
+                            }
+                        }
+                        ExprNode::Val(Value::Bool(false)) => {
+                            self.lint(&lint::UNREACHABLE_CODE, Some(func),
+                                Diagnostic::warning(lint::UNREACHABLE_CODE.name, format!("unreachable then branch in function '{func}'"))
+                                    .with_primary(ifNode.span, "condition is always false"));
+                            if let Some(elseBody) = &ifNode.elseBody {
+                                self.check_dead_code_block(elseBody, func);
+                            }
+                        }
+                        _ => {
+                            self.check_dead_code_block(&ifNode.ifBody, func);
+                            if let Some(elseBody) = &ifNode.elseBody {
+                                self.check_dead_code_block(elseBody, func);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_missing_returns_program(&self) {
+        // every call is part of an expression, so a called function is used as a value
+        let mut callees = vec![];
+        for rc_func in &self.program.func_nodes {
+            rc_func.block_node.collect_calls(&mut callees);
+        }
+
+        for rc_func in &self.program.func_nodes {
+            let block = &rc_func.block_node;
+            // the type checker reports functions with an annotated return type
+            if !Self::contains_return(block) || Self::always_returns(block) || Self::annotated_return(rc_func).is_some() {
+                continue;
+            }
+            let message = format!("function '{name}' returns a value on some paths but not on others", name = rc_func.name);
+            let label = "may reach the end of its body without returning";
+            if callees.contains(&rc_func.name) {
+                self.report(Diagnostic::error("E0301", message).with_primary(rc_func.span, label));
+            } else {
+                self.lint(&lint::MISSING_RETURN, Some(&rc_func.name),
+                    Diagnostic::warning(lint::MISSING_RETURN.name, message)
+                        .with_primary(rc_func.span, label)
+                        .with_note(format!("'{name}' is never called, so its missing value is not used", name = rc_func.name)));
+            }
+        }
+    }
+
+    fn contains_return(block: &BlockNode) -> bool {
+        block.statements.iter().any(|rc_stmt| match rc_stmt.deref() {
+            StmtNode::Return(_) => true,
+            StmtNode::While(whileNode) => Self::contains_return(&whileNode.body),
+            StmtNode::IfElse(ifNode) => {
+                Self::contains_return(&ifNode.ifBody)
+                    || ifNode.elseBody.as_ref().is_some_and(|elseBody| Self::contains_return(elseBody))
+            }
+            _ => false,
+        })
+    }
+
+    // true if control never reaches the end of the block
+    fn always_returns(block: &BlockNode) -> bool {
+        block.statements.iter().any(|rc_stmt| match rc_stmt.deref() {
+            StmtNode::Return(_) => true,
+            // without break, a loop on a constant true condition is only left by return
+            StmtNode::While(whileNode) => matches!(whileNode.condition.deref(), ExprNode::Val(Value::Bool(true))),
+            StmtNode::IfElse(ifNode) => {
+                let then_returns = Self::always_returns(&ifNode.ifBody);
+                let else_returns = ifNode.elseBody.as_ref().is_some_and(|elseBody| Self::always_returns(elseBody));
+                match ifNode.condition.deref() {
+                    ExprNode::Val(Value::Bool(true)) => then_returns,
+                    ExprNode::Val(Value::Bool(false)) => else_returns,
+                    _ => then_returns && else_returns,
+                }
+            }
+            _ => false,
+        })
+    }
+
+    // walks the call graph from main, like Executor starts from main
+    fn check_unused_functions_program(&self) {
+        let funcs: HashMap<&String, &Rc<FuncNode>> = self.program.func_nodes.iter()
+            .map(|rc_func| (&rc_func.name, rc_func))
+            .collect();
+        let Some(main) = funcs.get(&"main".to_string()) else {
+            return;
+        };
+
+        let mut reached = vec![main.name.clone()];
+        let mut pending = vec![*main];
+        while let Some(rc_func) = pending.pop() {
+            let mut callees = vec![];
+            rc_func.block_node.collect_calls(&mut callees);
+            for callee in callees {
+                if let Some(rc_callee) = funcs.get(&callee) {
+                    if !reached.contains(&callee) {
+                        reached.push(callee);
+                        pending.push(rc_callee);
+                    }
+                }
+            }
+        }
+
+        for rc_func in &self.program.func_nodes {
+            if !reached.contains(&rc_func.name) {
+                self.lint(&lint::UNUSED_FUNCTION, Some(&rc_func.name),
+                    Diagnostic::warning(lint::UNUSED_FUNCTION.name, format!("function '{name}' is never called from main", name = rc_func.name))
+                        .with_primary(rc_func.span, "never called"));
+            }
+        }
+    }
+
+    fn check_shadowing_program(&self) {
+        let globals = self.program.symbols.borrow();
+        for rc_func in &self.program.func_nodes {
+            for param in &rc_func.parameters {
+                if let Some(outer) = globals.map.get(&param.name) {
+                    self.report_shadowing(&param.name, param.span, outer, &rc_func.name, Analyzer::kind_of(outer, rc_func, true));
+                }
+            }
+            self.check_shadowing_block(&rc_func.block_node, rc_func);
+        }
+    }
+
+    fn check_shadowing_block(&self, block: &BlockNode, rc_func: &FuncNode) {
+        let parent = block.symbols.borrow().parent.clone();
+        for rc_stmt in &block.statements {
+            match rc_stmt.deref() {
+                StmtNode::Let(letNode) => {
+                    let outer = parent.as_ref().and_then(|rc_parent| rc_parent.borrow().lookup(&letNode.name));
+                    if let Some(outer) = outer {
+                        let global = self.program.symbols.borrow().map.get(&letNode.name).is_some_and(|global| global.span == outer.span);
+                        self.report_shadowing(&letNode.name, letNode.span, &outer, &rc_func.name, Analyzer::kind_of(&outer, rc_func, global));
+                    }
+                }
+                StmtNode::While(whileNode) => self.check_shadowing_block(&whileNode.body, rc_func),
+                StmtNode::IfElse(ifNode) => {
+                    self.check_shadowing_block(&ifNode.ifBody, rc_func);
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        self.check_shadowing_block(elseBody, rc_func);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // what the shadowed declaration is, to name it in the warning
+    fn kind_of(outer: &Symbol, rc_func: &FuncNode, global: bool) -> &'static str {
+        match outer.value {
+            Value::Func(_, _) => "function",
+            _ if global => "global variable",
+            _ if rc_func.parameters.iter().any(|param| param.span == outer.span) => "parameter",
+            _ => "variable of an enclosing block",
+        }
+    }
+
+    fn report_shadowing(&self, name: &str, span: Span, outer: &Symbol, func: &str, kind: &str) {
+        self.lint(&lint::SHADOWING, Some(func),
+            Diagnostic::warning(lint::SHADOWING.name, format!("'{name}' shadows a {kind} of the same name in function '{func}'"))
+                .with_primary(span, &format!("'{name}' declared again here"))
+                .with_secondary(outer.span, &format!("shadowed {kind} declared here")));
+    }
+
+    fn mark_tail_calls_program(&self) {
+        for rc_func in &self.program.func_nodes {
+            self.mark_tail_calls_block(&rc_func.block_node, rc_func);
+        }
+    }
+
+    // a call is in tail position if its result is returned right away,
+    // calls look up their function among the globals only. The result of the last function
+    // of a chain of tail calls is checked against its return type, so a function with an
+    // annotated return type only tail calls functions annotated with the same type
+    fn mark_tail_calls_block(&self, block: &BlockNode, rc_func: &FuncNode) {
+        for rc_stmt in &block.statements {
+            match rc_stmt.deref() {
+                StmtNode::Return(returnNode) => {
+                    if let ExprNode::Call(name, ..) = returnNode.expr.deref() {
+                        let callee = self.program.symbols.borrow().lookup(name);
+                        if let Some(Symbol { value: Value::Func(rc_callee, _), .. }) = callee {
+                            if rc_func.return_type.is_none_or(|ret| ret.is_dynamic()) || rc_callee.return_type == rc_func.return_type {
+                                returnNode.tail_call.set(true);
+                            }
+                        }
+                    }
+                }
+                StmtNode::While(whileNode) => self.mark_tail_calls_block(&whileNode.body, rc_func),
+                StmtNode::IfElse(ifNode) => {
+                    self.mark_tail_calls_block(&ifNode.ifBody, rc_func);
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        self.mark_tail_calls_block(elseBody, rc_func);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // the global frame holds the global variables followed by the functions, a function frame
+    // its parameters followed by the variables declared in its body
+    fn resolve_slots_program(&self) {
+        let mut globals = HashMap::new();
+        let names = self.program.let_nodes.iter().map(|rc_let| &rc_let.name)
+            .chain(self.program.func_nodes.iter().map(|rc_func| &rc_func.name));
+        for (index, name) in names.enumerate() {
+            globals.entry(name.clone()).or_insert(index);
+        }
+
+        let mut scopes = vec![globals];
+        for rc_func in &self.program.func_nodes {
+            let mut params = HashMap::new();
+            for param in &rc_func.parameters {
+                let index = params.len();
+                params.entry(param.name.clone()).or_insert(index);
+            }
+            scopes.push(params);
+            Self::resolve_slots_block(&rc_func.block_node, &mut scopes);
+            scopes.pop();
+        }
+    }
+
+    // like in the symbol table of a block, its variables are visible in all of the block,
+    // the block frame has a slot for each of them
+    fn resolve_slots_block(block: &BlockNode, scopes: &mut Vec<HashMap<String, usize>>) {
+        let scope = scopes.last_mut().unwrap();
+        for rc_stmt in &block.statements {
+            if let StmtNode::Let(letNode) = rc_stmt.deref() {
+                let next = scope.len();
+                let index = *scope.entry(letNode.name.clone()).or_insert(next);
+                letNode.slot.set(Some(Slot { depth: 0, index }));
+            }
+        }
+        block.slots.set(scope.len());
+
+        for rc_stmt in &block.statements {
+            match rc_stmt.deref() {
+                StmtNode::Let(_) => {}
+                StmtNode::Assign(assignNode) => {
+                    assignNode.slot.set(Self::resolve_slot(&assignNode.name, scopes));
+                    Self::resolve_slots_expression(&assignNode.expr, scopes);
+                }
+                StmtNode::Return(returnNode) => Self::resolve_slots_expression(&returnNode.expr, scopes),
+                StmtNode::Print(printNode) => Self::resolve_slots_expression(&printNode.expr, scopes),
+                StmtNode::While(whileNode) => {
+                    Self::resolve_slots_expression(&whileNode.condition, scopes);
+                    scopes.push(HashMap::new());
+                    Self::resolve_slots_block(&whileNode.body, scopes);
+                    scopes.pop();
+                }
+                StmtNode::IfElse(ifNode) => {
+                    Self::resolve_slots_expression(&ifNode.condition, scopes);
+                    scopes.push(HashMap::new());
+                    Self::resolve_slots_block(&ifNode.ifBody, scopes);
+                    scopes.pop();
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        scopes.push(HashMap::new());
+                        Self::resolve_slots_block(elseBody, scopes);
+                        scopes.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    // calls look up their function among the globals only
+    fn resolve_slots_expression(expr: &ExprNode, scopes: &[HashMap<String, usize>]) {
+        match expr {
+            ExprNode::Var(name, _, slot) => slot.set(Self::resolve_slot(name, scopes)),
+            ExprNode::Val(_) | ExprNode::String(_) => {}
+            ExprNode::Call(name, args, _, slot) => {
+                slot.set(scopes[0].get(name).map(|&index| Slot { depth: scopes.len() - 1, index }));
+                for arg in args {
+                    Self::resolve_slots_expression(arg, scopes);
+                }
+            }
+            ExprNode::Add(a, b) | ExprNode::Sub(a, b) | ExprNode::Mul(a, b) | ExprNode::Div(a, b)
+            | ExprNode::LessThan(a, b) | ExprNode::GreaterThan(a, b) | ExprNode::EqualTo(a, b)
+            | ExprNode::LessThanEq(a, b) | ExprNode::GreaterThanEq(a, b) | ExprNode::NotEqualTo(a, b)
+            | ExprNode::And(a, b) | ExprNode::Or(a, b) => {
+                Self::resolve_slots_expression(a, scopes);
+                Self::resolve_slots_expression(b, scopes);
+            }
+            ExprNode::Not(a) => Self::resolve_slots_expression(a, scopes),
+        }
+    }
+
+    // the innermost scope declaring the name, counted from the innermost frame
+    fn resolve_slot(name: &str, scopes: &[HashMap<String, usize>]) -> Option<Slot> {
+        scopes.iter().rev().enumerate()
+            .find_map(|(depth, scope)| scope.get(name).map(|&index| Slot { depth, index }))
+    }
+
+    fn check_types_program(&self) {
+        let mut scopes = vec![HashMap::new()];
+        for rc_let in &self.program.let_nodes {
+            let declared = self.types.borrow().globals.get(&rc_let.name).copied().unwrap_or(Type::Dynamic);
+            self.check_types_let(&rc_let.name, declared, &rc_let.value, rc_let.span, &mut scopes);
+        }
+
+        for rc_func in &self.program.func_nodes {
+            self.check_types_function(rc_func.clone(), &mut scopes);
+        }
+    }
+
+    fn check_types_function(&self, rc_func: Rc<FuncNode>, scopes: &mut Vec<HashMap<String, Type>>) {
+        let signature = self.signature(&rc_func.name);
+        let mut locals = HashMap::new();
+        for (param, param_type) in rc_func.parameters.iter().zip(&signature.params) {
+            locals.insert(param.name.clone(), *param_type);
+        }
+
+        scopes.push(locals);
+        self.check_types_block(&rc_func.block_node, &rc_func, signature.ret, scopes);
+        scopes.pop();
+
+        // falling off the end returns nil, which an annotated return type does not allow
+        if let Some(ret) = Self::annotated_return(&rc_func) {
+            if !Self::always_returns(&rc_func.block_node) {
+                let name = &rc_func.name;
+                self.report(
+                    Diagnostic::error("E0201", format!("function '{name}' returns {ret} but may reach the end of its body without returning a value"))
+                        .with_primary(rc_func.span, &format!("return type of '{name}' is {ret}"))
+                        .with_help("return a value at the end of the function".to_string()));
+            }
+        }
+    }
+
+    // the declared return type of a function if it rules out nil
+    fn annotated_return(rc_func: &FuncNode) -> Option<Type> {
+        rc_func.return_type.filter(|ret| *ret != Type::Nil && !ret.is_dynamic())
+    }
+
+    fn check_types_block(&self, block: &BlockNode, rc_func: &Rc<FuncNode>, ret: Type, scopes: &mut Vec<HashMap<String, Type>>) {
+        let func = &rc_func.name;
+        for rc_stmt in &block.statements {
+            self.statement.set(rc_stmt.span());
+            match rc_stmt.deref() {
+                StmtNode::Let(letNode) => {
+                    let declared = self.types.borrow().let_type(letNode);
+                    self.check_types_let(&letNode.name, declared, &letNode.value, letNode.span, scopes);
+                }
+                StmtNode::Assign(assignNode) => {
+                    let expected = Self::lookup_type(&assignNode.name, scopes);
+                    let found = self.check_types_expression(&assignNode.expr, func, scopes);
+                    if !expected.is_compatible(&found) {
+                        self.report(
+                            Diagnostic::error("E0201", format!("cannot assign {found} to variable '{name}' of type {expected}", name = assignNode.name))
+                                .with_primary(self.span_of(&assignNode.expr), &format!("expected {expected}, found {found}")));
+                    }
+                }
+                StmtNode::Return(returnNode) => {
+                    let found = self.check_types_expression(&returnNode.expr, func, scopes);
+                    if !ret.is_compatible(&found) {
+                        self.report(
+                            Diagnostic::error("E0201", format!("function '{func}' returns {ret} but this returns {found}"))
+                                .with_primary(self.span_of(&returnNode.expr), &format!("expected {ret}, found {found}"))
+                                .with_secondary(rc_func.span, &format!("return type of '{func}' is {ret}")));
+                    }
+                }
+                StmtNode::Print(printNode) => {
+                    self.check_types_expression(&printNode.expr, func, scopes);
+                }
+                StmtNode::While(whileNode) => {
+                    self.check_types_condition("while", &whileNode.condition, whileNode.condition_span, func, scopes);
+                    scopes.push(HashMap::new());
+                    self.check_types_block(&whileNode.body, rc_func, ret, scopes);
+                    scopes.pop();
+                }
+                StmtNode::IfElse(ifNode) => {
+                    self.check_types_condition("if", &ifNode.condition, ifNode.condition_span, func, scopes);
+                    scopes.push(HashMap::new());
+                    self.check_types_block(&ifNode.ifBody, rc_func, ret, scopes);
+                    scopes.pop();
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        scopes.push(HashMap::new());
+                        self.check_types_block(elseBody, rc_func, ret, scopes);
+                        scopes.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_types_let(&self, name: &str, declared: Type, value: &Value, span: Span, scopes: &mut [HashMap<String, Type>]) {
+        let found = Type::of(value);
+        if !declared.is_compatible(&found) {
+            self.report(
+                Diagnostic::error("E0201", format!("cannot initialize variable '{name}' of type {declared} with {found}"))
+                    .with_primary(span, &format!("expected {declared}, found {found}")));
+        }
+        if let Some(scope) = scopes.last_mut() {
+            scope.insert(name.to_string(), declared);
+        }
+    }
+
+    fn check_types_condition(&self, keyword: &str, condition: &ExprNode, span: Span, func: &str, scopes: &[HashMap<String, Type>]) {
+        let found = self.check_types_expression(condition, func, scopes);
+        if !Type::Bool.is_compatible(&found) {
+            self.report(
+                Diagnostic::error("E0201", format!("condition of '{keyword}' must be bool, found {found}"))
+                    .with_primary(span, &format!("expected bool, found {found}")));
+        }
+    }
+
+    fn check_types_expression(&self, expr: &ExprNode, func: &str, scopes: &[HashMap<String, Type>]) -> Type {
+        let result = match expr {
+            ExprNode::Var(name, ..) => Self::lookup_type(name, scopes),
+            ExprNode::Val(value) => Type::of(value),
+            ExprNode::String(_) => Type::Char,
+            ExprNode::Call(name, args, ..) => {
+                let arg_types: Vec<Type> = args.iter()
+                    .map(|arg| self.check_types_expression(arg, func, scopes))
+                    .collect();
+
+                let rc_callee = match self.program.symbols.borrow().map.get(name) {
+                    Some(Symbol { value: Value::Func(rc_callee, _), .. }) => Some(rc_callee.clone()),
+                    _ => None,
+                };
+                let Some(rc_callee) = rc_callee else {
+                    return Type::Dynamic;
+                };
+
+                // generic parameters take the type of their first concrete argument
+                let signature = self.signature(name);
+                let mut generics = HashMap::new();
+                let params = rc_callee.parameters.iter().zip(&signature.params);
+                for (((param, param_type), found), arg) in params.zip(arg_types).zip(args) {
+                    let expected = Self::instantiate(*param_type, &generics);
+                    if !expected.is_compatible(&found) {
+                        self.report(
+                            Diagnostic::error("E0201", format!("argument '{param}' of '{name}' expects {expected}, found {found}", param = param.name))
+                                .with_primary(self.span_of(arg), &format!("expected {expected}, found {found}"))
+                                .with_secondary(param.span, &format!("parameter '{param}' declared here", param = param.name)));
+                    }
+                    if let Type::Generic(g) = param_type {
+                        if !found.is_dynamic() {
+                            generics.entry(*g).or_insert(found);
+                        }
+                    }
+                }
+                Self::instantiate(signature.ret, &generics)
             }
+            ExprNode::Add(a, b) => self.check_types_binary("+", a, b, func, scopes),
+            ExprNode::Sub(a, b) => self.check_types_binary("-", a, b, func, scopes),
+            ExprNode::Mul(a, b) => self.check_types_binary("*", a, b, func, scopes),
+            ExprNode::Div(a, b) => self.check_types_binary("/", a, b, func, scopes),
+            ExprNode::LessThan(a, b) => self.check_types_binary("<", a, b, func, scopes),
+            ExprNode::GreaterThan(a, b) => self.check_types_binary(">", a, b, func, scopes),
+            ExprNode::EqualTo(a, b) => self.check_types_binary("==", a, b, func, scopes),
+            ExprNode::LessThanEq(a, b) => self.check_types_binary("<=", a, b, func, scopes),
+            ExprNode::GreaterThanEq(a, b) => self.check_types_binary(">=", a, b, func, scopes),
+            ExprNode::NotEqualTo(a, b) => self.check_types_binary("!=", a, b, func, scopes),
+            ExprNode::And(a, b) => self.check_types_binary("and", a, b, func, scopes),
+            ExprNode::Or(a, b) => self.check_types_binary("or", a, b, func, scopes),
+            ExprNode::Not(a) => {
+                let operand = self.check_types_expression(a, func, scopes);
+                if !operand.is_compatible(&Type::Bool) {
+                    self.report(
+                        Diagnostic::error("E0202", format!("cannot apply 'not' to {operand}"))
+                            .with_primary(self.span_of(expr), &format!("this is {operand}")));
+                }
+                Type::Bool
+            }
+        };
+        // remembered for the optimizer, which only rewrites expressions of a known type
+        self.types.borrow_mut().set_expr_type(expr, result);
+        result
+    }
+
+    fn check_types_binary(&self, op: &str, a: &ExprNode, b: &ExprNode, func: &str, scopes: &[HashMap<String, Type>]) -> Type {
+        let left = self.check_types_expression(a, func, scopes);
+        let right = self.check_types_expression(b, func, scopes);
+        match Type::binary(op, left, right) {
+            Some(result) => result,
+            None => {
+                let span = match (a.span(), b.span()) {
+                    (Some(span_a), Some(span_b)) => span_a.to(span_b),
+                    (span_a, span_b) => span_a.or(span_b).unwrap_or(self.statement.get()),
+                };
+                self.report(
+                    Diagnostic::error("E0202", format!("cannot apply '{op}' to {left} and {right}"))
+                        .with_primary(span, &format!("{left} {op} {right} is not defined")));
+                Type::Dynamic
+            }
+        }
+    }
+
+    fn signature(&self, name: &String) -> Signature {
+        match self.types.borrow().signatures.get(name) {
+            Some(signature) => signature.clone(),
+            None => Signature { params: vec![], ret: Type::Dynamic },
+        }
+    }
+
+    fn instantiate(t: Type, generics: &HashMap<usize, Type>) -> Type {
+        match t {
+            Type::Generic(g) => generics.get(&g).copied().unwrap_or(Type::Dynamic),
+            t => t,
+        }
+    }
+
+    fn lookup_type(name: &str, scopes: &[HashMap<String, Type>]) -> Type {
+        for scope in scopes.iter().rev() {
+            if let Some(t) = scope.get(name) {
+                return *t;
+            }
+        }
+        Type::Dynamic
+    }
+}
+
+// local variables of a function and whether they are assigned on every path, innermost scope last
+#[derive(Clone)]
+struct Assigned {
+    // the outermost scope holds the globals
+    scopes: Vec<HashMap<String, bool>>,
+    // false after a return, nothing is read on such a path
+    reachable: bool,
+    // globals a call of a function may assign
+    assigned_by: Rc<HashMap<String, Vec<String>>>,
+}
+
+impl Assigned {
+    fn declare(&mut self, name: &str, assigned: bool) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), assigned);
+    }
+
+    fn assign(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.iter_mut().rev().find(|scope| scope.contains_key(name)) {
+            scope.insert(name.to_string(), true);
+        }
+    }
+
+    // the globals the called function may assign count as assigned after the call
+    fn assign_globals_of(&mut self, callee: &str) {
+        if let Some(names) = self.assigned_by.clone().get(callee) {
+            for name in names {
+                self.scopes[0].insert(name.clone(), true);
+            }
+        }
+    }
+
+    fn is_assigned(&self, name: &str) -> bool {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).copied().unwrap_or(true)
+    }
+
+    // state after two paths meet, a variable stays assigned only if both paths assigned it
+    fn join(self, other: Assigned) -> Assigned {
+        if !self.reachable {
+            return other;
+        }
+        if !other.reachable {
+            return self;
         }
+        let scopes = self.scopes.into_iter().zip(other.scopes)
+            .map(|(scope, other_scope)| {
+                scope.into_iter()
+                    .map(|(name, assigned)| {
+                        let other_assigned = other_scope.get(&name).copied().unwrap_or(false);
+                        (name, assigned && other_assigned)
+                    })
+                    .collect()
+            })
+            .collect();
+        Assigned { scopes, reachable: true, assigned_by: self.assigned_by }
     }
 }
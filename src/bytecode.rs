@@ -0,0 +1,384 @@
+use std::rc::Rc;
+
+use crate::evaluator::{ArithmeticOp, RelationalOp};
+use crate::executor::Executor;
+use crate::logger::Logger;
+use crate::span::Span;
+use crate::tree::{BlockNode, ExprNode, FuncNode, ProgramNode, Slot, StmtNode};
+use crate::value::Value;
+
+/// Instruction of the virtual machine. Operands index the constants, the locals of the
+/// running function, the globals, the call sites of the function or its code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    // counts steps like the tree walker does, where they run out is located
+    // at the step spans of the function from the given index on
+    Step(usize, usize),
+    Const(usize),
+    Nil,
+    Load(usize),
+    Store(usize),
+    LoadGlobal(usize),
+    StoreGlobal(usize),
+    // resets the locals of a block that is entered again
+    Clear(usize, usize),
+    Arithmetic(ArithmeticOp),
+    Relational(RelationalOp),
+    Equal,
+    NotEqual,
+    Not,
+    // keeps the left operand of and / or and jumps if it decides the result
+    And(usize),
+    Or(usize),
+    // checks that the right operand of the named logical operator is a bool
+    Logical(&'static str),
+    Jump(usize),
+    // pops the condition of the if or while statement named by its keyword, which must be a bool
+    JumpIfFalse(usize, &'static str),
+    // checks that the value on top of the stack is a function before its arguments are evaluated
+    Callee(usize),
+    Call(usize),
+    TailCall(usize),
+    Return,
+    Print,
+}
+
+/// A call in the code of a function.
+#[derive(Debug, Clone)]
+pub struct CallSite {
+    pub name: String,
+    pub argc: usize,
+    pub span: Span,
+    // function the callee is when the program starts, globals may be assigned other functions
+    pub func: Option<usize>,
+}
+
+/// Code of one script function.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub node: Rc<FuncNode>,
+    pub code: Vec<Op>,
+    // where a runtime error in each instruction is located, like the tree walker locates it
+    pub spans: Vec<Span>,
+    pub step_spans: Vec<Span>,
+    pub calls: Vec<CallSite>,
+    // parameters first, then the variables of the body and of its nested blocks
+    pub frame_size: usize,
+}
+
+/// A compiled program: the constants pool, the initial values of the globals and the functions.
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    // the program compiled, which names the globals
+    pub program: Rc<ProgramNode>,
+    pub constants: Vec<Value>,
+    pub globals: Vec<Value>,
+    pub functions: Vec<Function>,
+}
+
+impl Bytecode {
+    /// Index of the code of a function value, looked up at the given index first.
+    pub fn function_index(&self, rc_func: &Rc<FuncNode>, hint: Option<usize>) -> usize {
+        match hint {
+            Some(index) if Rc::ptr_eq(&self.functions[index].node, rc_func) => index,
+            _ => self.functions.iter()
+                .position(|function| Rc::ptr_eq(&function.node, rc_func))
+                .expect("function value of the compiled program"),
+        }
+    }
+}
+
+/// Compiles an analyzed program into bytecode for the virtual machine. The variables are
+/// found by the slots the analyzer resolved, nested block frames become ranges of locals.
+pub struct Compiler {
+    program: Rc<ProgramNode>,
+    constants: Vec<Value>,
+    globals: Vec<Value>,
+    function: Option<Function>,
+    // first local of the function frame and of every enclosing block frame, innermost last
+    scopes: Vec<usize>,
+    // first local not used by the enclosing blocks
+    next: usize,
+    // spans of the statement and the expressions being compiled, innermost last
+    locations: Vec<Span>,
+    // steps of the tree walker not counted yet, instructions that cannot fail
+    // and have no visible effect do not need to wait for them
+    steps: Vec<Span>,
+}
+
+impl Compiler {
+    pub fn new(program: Rc<ProgramNode>) -> Compiler {
+        Compiler {
+            globals: Executor::global_values(&program),
+            program,
+            constants: vec![],
+            function: None,
+            scopes: vec![],
+            next: 0,
+            locations: vec![],
+            steps: vec![],
+        }
+    }
+
+    pub fn compile(mut self) -> Bytecode {
+        Logger::debug("Compile bytecode.");
+        let functions = self.program.clone().func_nodes.iter()
+            .map(|rc_func| self.compile_function(rc_func))
+            .collect();
+        Bytecode { program: self.program, constants: self.constants, globals: self.globals, functions }
+    }
+
+    fn compile_function(&mut self, rc_func: &Rc<FuncNode>) -> Function {
+        let slots = rc_func.block_node.slots.get();
+        self.function = Some(Function {
+            node: rc_func.clone(),
+            code: vec![],
+            spans: vec![],
+            step_spans: vec![],
+            calls: vec![],
+            frame_size: slots,
+        });
+        self.scopes = vec![0];
+        self.next = slots;
+
+        // the parameters are in place when the function starts, its variables are nil
+        self.locations.push(rc_func.span);
+        self.compile_block(&rc_func.block_node);
+        // falling off the end returns nil
+        self.emit(Op::Nil);
+        self.emit(Op::Return);
+        self.locations.pop();
+
+        self.function.take().unwrap()
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.function.as_mut().unwrap()
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        if !matches!(op, Op::Const(_) | Op::Nil | Op::Load(_) | Op::Store(_) | Op::LoadGlobal(_) | Op::StoreGlobal(_) | Op::Clear(_, _)) {
+            self.flush_steps();
+        }
+        let location = *self.locations.last().unwrap();
+        let function = self.function();
+        function.code.push(op);
+        function.spans.push(location);
+        function.code.len() - 1
+    }
+
+    fn step(&mut self) {
+        self.steps.push(*self.locations.last().unwrap());
+    }
+
+    fn flush_steps(&mut self) {
+        if self.steps.is_empty() {
+            return;
+        }
+        let steps = std::mem::take(&mut self.steps);
+        let function = self.function();
+        let op = Op::Step(steps.len(), function.step_spans.len());
+        function.step_spans.extend(steps);
+        function.code.push(op);
+        function.spans.push(function.step_spans[function.step_spans.len() - 1]);
+    }
+
+    // position of the next instruction as target of a jump, the steps before it are counted on every path
+    fn label(&mut self) -> usize {
+        self.flush_steps();
+        self.function().code.len()
+    }
+
+    fn patch(&mut self, jump: usize, target: usize) {
+        match &mut self.function().code[jump] {
+            Op::And(to) | Op::Or(to) | Op::Jump(to) | Op::JumpIfFalse(to, _) => *to = target,
+            op => panic!("cannot patch {op:?}"),
+        }
+    }
+
+    fn constant(&mut self, value: Value) -> Op {
+        self.constants.push(value);
+        Op::Const(self.constants.len() - 1)
+    }
+
+    // globals are one frame further up than the function frame
+    fn load(&self, slot: Option<Slot>) -> Op {
+        match slot {
+            None => Op::Nil,
+            Some(Slot { depth, index }) if depth == self.scopes.len() => Op::LoadGlobal(index),
+            Some(Slot { depth, index }) => Op::Load(self.scopes[self.scopes.len() - 1 - depth] + index),
+        }
+    }
+
+    fn store(&self, slot: Slot) -> Op {
+        match self.load(Some(slot)) {
+            Op::LoadGlobal(index) => Op::StoreGlobal(index),
+            Op::Load(index) => Op::Store(index),
+            op => unreachable!("{op:?}"),
+        }
+    }
+
+    fn compile_block(&mut self, block: &BlockNode) {
+        for rc_stmt in &block.statements {
+            self.locations.push(rc_stmt.span());
+            self.step();
+            self.compile_statement(rc_stmt);
+            self.locations.pop();
+        }
+    }
+
+    // a block nested in a function gets locals after those of the blocks enclosing it,
+    // which start out nil on every entry like a new block frame does
+    fn compile_nested_block(&mut self, block: &BlockNode) {
+        let base = self.next;
+        let slots = block.slots.get();
+        self.scopes.push(base);
+        self.next += slots;
+        let frame_size = self.next;
+        let function = self.function();
+        function.frame_size = function.frame_size.max(frame_size);
+
+        if slots > 0 {
+            self.emit(Op::Clear(base, slots));
+        }
+        self.compile_block(block);
+
+        self.scopes.pop();
+        self.next = base;
+    }
+
+    fn compile_statement(&mut self, stmt: &StmtNode) {
+        match stmt {
+            StmtNode::Let(letNode) => {
+                let op = self.constant(letNode.value.clone());
+                self.emit(op);
+                let slot = letNode.slot.get().expect("let statement resolved by the analyzer");
+                let op = self.store(slot);
+                self.emit(op);
+            }
+            StmtNode::Assign(assignNode) => {
+                self.compile_expression(&assignNode.expr, false);
+                let slot = assignNode.slot.get().expect("assignment resolved by the analyzer");
+                let op = self.store(slot);
+                self.emit(op);
+            }
+            StmtNode::Return(returnNode) => {
+                self.compile_expression(&returnNode.expr, returnNode.tail_call.get());
+                self.emit(Op::Return);
+            }
+            StmtNode::Print(printNode) => {
+                self.compile_expression(&printNode.expr, false);
+                self.emit(Op::Print);
+            }
+            StmtNode::While(whileNode) => {
+                let start = self.label();
+                self.compile_expression(&whileNode.condition, false);
+                let exit = self.emit(Op::JumpIfFalse(0, "while"));
+                self.compile_nested_block(&whileNode.body);
+                self.emit(Op::Jump(start));
+                let end = self.label();
+                self.patch(exit, end);
+            }
+            StmtNode::IfElse(ifNode) => {
+                self.compile_expression(&ifNode.condition, false);
+                let skip = self.emit(Op::JumpIfFalse(0, "if"));
+                self.compile_nested_block(&ifNode.ifBody);
+                match &ifNode.elseBody {
+                    None => {
+                        let end = self.label();
+                        self.patch(skip, end);
+                    }
+                    Some(elseBody) => {
+                        let exit = self.emit(Op::Jump(0));
+                        let start = self.label();
+                        self.patch(skip, start);
+                        self.compile_nested_block(elseBody);
+                        let end = self.label();
+                        self.patch(exit, end);
+                    }
+                }
+            }
+        }
+    }
+
+    // a call marked as tail call by the analyzer replaces the running function
+    fn compile_expression(&mut self, expr: &ExprNode, tail_call: bool) {
+        let span = expr.span();
+        if let Some(span) = span {
+            self.locations.push(span);
+        }
+        self.step();
+
+        match expr {
+            ExprNode::Var(_, _, slot) => {
+                let op = self.load(slot.get());
+                self.emit(op);
+            }
+            ExprNode::Val(value) => {
+                let op = self.constant(value.clone());
+                self.emit(op);
+            }
+            ExprNode::String(value) => {
+                let op = self.constant(Value::Chars(value.clone()));
+                self.emit(op);
+            }
+            ExprNode::Call(name, args, span, slot) => {
+                let op = self.load(slot.get());
+                self.emit(op);
+                let func = match op {
+                    Op::LoadGlobal(index) => match &self.globals[index] {
+                        Value::Func(rc_func, _) => self.program.func_nodes.iter().position(|rc_node| Rc::ptr_eq(rc_node, rc_func)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let site = CallSite { name: name.clone(), argc: args.len(), span: *span, func };
+                let function = self.function();
+                function.calls.push(site);
+                let site = function.calls.len() - 1;
+
+                self.emit(Op::Callee(site));
+                for arg in args {
+                    self.compile_expression(arg, false);
+                }
+                self.emit(if tail_call { Op::TailCall(site) } else { Op::Call(site) });
+            }
+            ExprNode::Add(a, b) => self.compile_binary(a, b, Op::Arithmetic(ArithmeticOp::Add)),
+            ExprNode::Sub(a, b) => self.compile_binary(a, b, Op::Arithmetic(ArithmeticOp::Sub)),
+            ExprNode::Mul(a, b) => self.compile_binary(a, b, Op::Arithmetic(ArithmeticOp::Mul)),
+            ExprNode::Div(a, b) => self.compile_binary(a, b, Op::Arithmetic(ArithmeticOp::Div)),
+            ExprNode::LessThan(a, b) => self.compile_binary(a, b, Op::Relational(RelationalOp::LessThan)),
+            ExprNode::GreaterThan(a, b) => self.compile_binary(a, b, Op::Relational(RelationalOp::GreaterThan)),
+            ExprNode::LessThanEq(a, b) => self.compile_binary(a, b, Op::Relational(RelationalOp::LessThanEqual)),
+            ExprNode::GreaterThanEq(a, b) => self.compile_binary(a, b, Op::Relational(RelationalOp::GreaterThanEqual)),
+            ExprNode::EqualTo(a, b) => self.compile_binary(a, b, Op::Equal),
+            ExprNode::NotEqualTo(a, b) => self.compile_binary(a, b, Op::NotEqual),
+            // the right operand is only evaluated if the left one does not decide the result
+            ExprNode::And(a, b) => self.compile_logical(a, b, Op::And(0), "and"),
+            ExprNode::Or(a, b) => self.compile_logical(a, b, Op::Or(0), "or"),
+            ExprNode::Not(a) => {
+                self.compile_expression(a, false);
+                self.emit(Op::Not);
+            }
+        }
+
+        if span.is_some() {
+            self.locations.pop();
+        }
+    }
+
+    fn compile_binary(&mut self, a: &Rc<ExprNode>, b: &Rc<ExprNode>, op: Op) {
+        self.compile_expression(a, false);
+        self.compile_expression(b, false);
+        self.emit(op);
+    }
+
+    fn compile_logical(&mut self, a: &Rc<ExprNode>, b: &Rc<ExprNode>, op: Op, name: &'static str) {
+        self.compile_expression(a, false);
+        let jump = self.emit(op);
+        self.compile_expression(b, false);
+        self.emit(Op::Logical(name));
+        let end = self.label();
+        self.patch(jump, end);
+    }
+}
@@ -0,0 +1,226 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::diagnostic::Diagnostic;
+use crate::evaluator::Overflow;
+use crate::runtime_error::{RuntimeError, RuntimeResult};
+use crate::value::Value;
+
+/// Calls of script functions that may be active at the same time by default.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+// steps between two looks at the clock, reading it on every step would slow down execution
+const STEPS_PER_CLOCK_CHECK: u64 = 1024;
+
+/// Where the print statements of a program write to.
+#[derive(Debug, Clone, Default)]
+pub enum Output {
+    #[default]
+    Stdout,
+    /// Collects the printed lines, shared with whoever reads them afterwards.
+    Capture(Rc<RefCell<String>>),
+}
+
+/// Settings of one execution of a program, shared by the executor and the evaluator.
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub overflow: Overflow,
+    pub max_call_depth: usize,
+    /// Statements and expressions the program may execute, unlimited if none.
+    pub fuel: Option<u64>,
+    /// Time the program may run, unlimited if none.
+    pub timeout: Option<Duration>,
+    /// Native stack the calls of script functions may take, calls are refused once they have
+    /// taken it. Unchecked if none.
+    pub stack_size: Option<usize>,
+    pub output: Output,
+    // calls of script functions that have not returned yet
+    depth: Cell<usize>,
+    steps: Cell<u64>,
+    deadline: Cell<Option<Instant>>,
+    // position on the native stack when execution started
+    stack_start: Cell<usize>,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context {
+            overflow: Overflow::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fuel: None,
+            timeout: None,
+            stack_size: None,
+            output: Output::default(),
+            depth: Cell::new(0),
+            steps: Cell::new(0),
+            deadline: Cell::new(None),
+            stack_start: Cell::new(0),
+        }
+    }
+
+    /// Starts the clock of the timeout and measures the stack from here.
+    pub fn start(&self) {
+        self.deadline.set(self.timeout.map(|timeout| Instant::now() + timeout));
+        self.stack_start.set(Self::stack_position());
+    }
+
+    /// Counts one step of execution, an error once the fuel is used up or the time is over.
+    pub fn step(&self) -> RuntimeResult<()> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+
+        if let Some(fuel) = self.fuel {
+            if steps > fuel {
+                return Err(RuntimeError::new(
+                    Diagnostic::error("E0509", format!("program ran out of fuel after {fuel} steps"))
+                        .with_help("use '--fuel' to allow more steps".to_string()))
+                    .with_label("out of fuel here"));
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline.get(), self.timeout) {
+            if steps.is_multiple_of(STEPS_PER_CLOCK_CHECK) && Instant::now() >= deadline {
+                return Err(RuntimeError::new(
+                    Diagnostic::error("E0510", format!("program exceeded its time limit of {} ms", timeout.as_millis()))
+                        .with_help("use '--timeout' to allow more time".to_string()))
+                    .with_label("stopped here"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts several steps at once like as many single steps, on an error with the number
+    /// of steps that were taken before it.
+    pub fn step_by(&self, count: u64) -> Result<(), (u64, RuntimeError)> {
+        let steps = self.steps.get();
+        let fuel_left = self.fuel.is_none_or(|fuel| steps + count <= fuel);
+        let clock_checked = self.deadline.get().is_some()
+            && steps / STEPS_PER_CLOCK_CHECK != (steps + count) / STEPS_PER_CLOCK_CHECK;
+        if fuel_left && !clock_checked {
+            self.steps.set(steps + count);
+            return Ok(());
+        }
+        for taken in 0..count {
+            self.step().map_err(|error| (taken, error))?;
+        }
+        Ok(())
+    }
+
+    /// Prints the value of a print statement on a line of its own.
+    pub fn print(&self, value: &Value) {
+        match &self.output {
+            Output::Stdout => println!("{}", value.format()),
+            Output::Capture(buffer) => {
+                let mut buffer = buffer.borrow_mut();
+                buffer.push_str(&value.format());
+                buffer.push('\n');
+            }
+        }
+    }
+
+    /// Enters a call, false if that exceeds the maximum call depth or leaves too little of the stack.
+    pub fn enter(&self) -> bool {
+        if self.depth.get() >= self.max_call_depth || self.stack_exhausted() {
+            return false;
+        }
+        self.depth.set(self.depth.get() + 1);
+        true
+    }
+
+    pub fn leave(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    /// Calls of script functions that have not returned yet.
+    pub fn depth(&self) -> usize {
+        self.depth.get()
+    }
+
+    // blocks and expressions nested in a function take stack too, so the maximum call depth alone
+    // does not keep the interpreter from overflowing it
+    fn stack_exhausted(&self) -> bool {
+        match self.stack_size {
+            Some(size) => Self::stack_position().abs_diff(self.stack_start.get()) > size,
+            None => false,
+        }
+    }
+
+    // address of a local variable, which moves as the stack grows
+    #[inline(never)]
+    fn stack_position() -> usize {
+        let marker = 0u8;
+        std::hint::black_box(&marker) as *const u8 as usize
+    }
+}
+
+impl Default for Context {
+    fn default() -> Context {
+        Context::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Context, STEPS_PER_CLOCK_CHECK};
+
+    #[test]
+    fn it_runs_out_of_fuel_after_the_given_steps() {
+        let mut context = Context::new();
+        context.fuel = Some(3);
+        context.start();
+        for _ in 0..3 {
+            assert!(context.step().is_ok());
+        }
+        assert_eq!(context.step().map_err(|error| error.code()), Err("E0509"));
+    }
+
+    #[test]
+    fn it_runs_out_of_fuel_within_a_batch_of_steps() {
+        let mut context = Context::new();
+        context.fuel = Some(5);
+        context.start();
+        assert!(context.step_by(3).is_ok());
+        assert_eq!(context.step_by(4).map_err(|(taken, error)| (taken, error.code())), Err((2, "E0509")));
+    }
+
+    #[test]
+    fn it_stops_at_the_deadline() {
+        let mut context = Context::new();
+        context.timeout = Some(Duration::ZERO);
+        context.start();
+        for _ in 1..STEPS_PER_CLOCK_CHECK {
+            assert!(context.step().is_ok());
+        }
+        assert_eq!(context.step().map_err(|error| error.code()), Err("E0510"));
+    }
+
+    #[test]
+    fn it_refuses_calls_that_leave_too_little_stack() {
+        // each call takes a KiB of stack, far less than the calls the maximum call depth allows fit
+        fn enter(context: &Context) -> usize {
+            if !context.enter() {
+                return 0;
+            }
+            let frame = std::hint::black_box([1u8; 1024]);
+            frame[0] as usize + enter(context)
+        }
+        let mut context = Context::new();
+        context.stack_size = Some(64 * 1024);
+        context.start();
+        let calls = enter(&context);
+        assert!(0 < calls && calls < 64);
+    }
+
+    #[test]
+    fn it_limits_the_call_depth() {
+        let mut context = Context::new();
+        context.max_call_depth = 2;
+        assert!(context.enter());
+        assert!(context.enter());
+        assert!(!context.enter());
+        context.leave();
+        assert!(context.enter());
+    }
+}
@@ -0,0 +1,319 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::fmt::Display;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// How diagnostics are printed on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Rendered like rustc, with the source lines they point at.
+    Human,
+    /// One JSON object per line, for editors and CI.
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    // the primary label marks the problem itself, secondary ones give context
+    pub primary: bool,
+}
+
+/// A problem found in the program by any phase, from parsing to execution.
+/// Errors carry a code like `E0106`, warnings the name of the lint that reported them.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    // suggestions how to fix the problem
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            labels: vec![],
+            notes: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn error(code: &'static str, message: String) -> Diagnostic {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: String) -> Diagnostic {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub fn with_primary(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.insert(0, Label { span, message: message.to_string(), primary: true });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.push(Label { span, message: message.to_string(), primary: false });
+        self
+    }
+
+    /// Span of the primary label, where the problem is.
+    pub fn span(&self) -> Option<Span> {
+        self.labels.iter().find(|label| label.primary).map(|label| label.span)
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_help(mut self, help: String) -> Diagnostic {
+        self.help.push(help);
+        self
+    }
+
+    /// Renders the diagnostic like rustc does, quoting the source lines its labels point at.
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+
+        let width = self.labels.iter().map(|label| label.span.line.to_string().len()).max().unwrap_or(1);
+        let gutter = " ".repeat(width);
+
+        if let Some(first) = self.labels.first() {
+            out += &format!("{gutter}--> {file}:{}\n", first.span);
+            out += &format!("{gutter} |\n");
+        }
+
+        // labels in source order, each under a quote of its line
+        let mut labels: Vec<&Label> = self.labels.iter().collect();
+        labels.sort_by_key(|label| (label.span.line, label.span.col));
+        let lines: Vec<&str> = source.lines().collect();
+        let mut quoted = 0;
+        for label in labels {
+            let marker = if label.primary { '^' } else { '-' };
+            // the end of input is located just past the last line
+            let line = match lines.get(label.span.line.wrapping_sub(1)) {
+                Some(line) => *line,
+                None if label.span.line == lines.len() + 1 => "",
+                None => continue,
+            };
+            if quoted != label.span.line {
+                out += format!("{:>width$} | {}", label.span.line, line).trim_end();
+                out += "\n";
+                quoted = label.span.line;
+            }
+
+            // keep tabs so the underline lines up with the quoted line
+            let indent: String = line.chars()
+                .take(label.span.col - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let rest = line.chars().count().saturating_sub(label.span.col - 1);
+            let length = (label.span.end - label.span.start).clamp(1, rest.max(1));
+            let underline = marker.to_string().repeat(length);
+            if label.message.is_empty() {
+                out += &format!("{gutter} | {indent}{underline}\n");
+            } else {
+                out += &format!("{gutter} | {indent}{underline} {}\n", label.message);
+            }
+        }
+
+        for note in &self.notes {
+            out += &format!("{gutter} = note: {note}\n");
+        }
+        for help in &self.help {
+            out += &format!("{gutter} = help: {help}\n");
+        }
+        out
+    }
+
+    /// Renders the diagnostic as a single line JSON object. Lines and columns start at 1,
+    /// the end of a range is exclusive. Diagnostics without a location have null positions.
+    pub fn to_json(&self, file: &str, source: &str) -> String {
+        let labels: Vec<String> = self.labels.iter()
+            .map(|label| format!(
+                "{{{},\"primary\":{},\"label\":{}}}",
+                json_range(Some(label.span), source), label.primary, json_string(&label.message)))
+            .collect();
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        let help: Vec<String> = self.help.iter().map(|help| json_string(help)).collect();
+
+        format!(
+            "{{\"file\":{},{},\"severity\":\"{}\",\"code\":{},\"message\":{},\"labels\":[{}],\"notes\":[{}],\"help\":[{}]}}",
+            json_string(file),
+            json_range(self.span(), source),
+            self.severity,
+            json_string(self.code),
+            json_string(&self.message),
+            labels.join(","),
+            notes.join(","),
+            help.join(","))
+    }
+}
+
+fn json_range(span: Option<Span>, source: &str) -> String {
+    match span {
+        None => "\"line\":null,\"column\":null,\"end_line\":null,\"end_column\":null".to_string(),
+        Some(span) => {
+            let (end_line, end_column) = position(source, span.end);
+            format!(
+                "\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}",
+                span.line, span.col, end_line, end_column)
+        }
+    }
+}
+
+// line and column of a char offset in the source
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut column = 1;
+    for c in source.chars().take(offset) {
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    (line, column)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Diagnostics of one source file, collected across all phases and printed in batches.
+pub struct Diagnostics {
+    file: String,
+    source: String,
+    pending: RefCell<Vec<Diagnostic>>,
+    errors: Cell<usize>,
+    format: MessageFormat,
+}
+
+impl Diagnostics {
+    pub fn new(file: &str, source: &str) -> Diagnostics {
+        Diagnostics {
+            file: file.to_string(),
+            source: source.to_string(),
+            pending: RefCell::new(vec![]),
+            errors: Cell::new(0),
+            format: MessageFormat::Human,
+        }
+    }
+
+    pub fn set_format(&mut self, format: MessageFormat) {
+        self.format = format;
+    }
+
+    pub fn format(&self) -> MessageFormat {
+        self.format
+    }
+
+    pub fn report(&self, diagnostic: Diagnostic) {
+        if diagnostic.severity == Severity::Error {
+            self.errors.set(self.errors.get() + 1);
+        }
+        self.pending.borrow_mut().push(diagnostic);
+    }
+
+    /// Number of errors reported so far, including the ones already flushed.
+    pub fn error_count(&self) -> usize {
+        self.errors.get()
+    }
+
+    /// Prints and forgets every diagnostic reported since the last flush.
+    pub fn flush(&self) {
+        for diagnostic in self.pending.borrow_mut().drain(..) {
+            match self.format {
+                MessageFormat::Human => eprintln!("{}", diagnostic.render(&self.file, &self.source)),
+                MessageFormat::Json => eprintln!("{}", diagnostic.to_json(&self.file, &self.source)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::span::Span;
+
+    use super::Diagnostic;
+
+    #[test]
+    fn it_renders_labels_under_their_source_lines() {
+        let source = "func add(a, b) [\n    return a + b;\n]\nfunc main() [\n    print add(1);\n]\n";
+        let call = Span { start: 55, end: 61, line: 5, col: 11 };
+        let func = Span { start: 5, end: 8, line: 1, col: 6 };
+        let diagnostic = Diagnostic::error("E0104", "function 'add' takes 2 argument(s) but 1 were supplied".to_string())
+            .with_primary(call, "called with 1 argument(s)")
+            .with_secondary(func, "'add' defined here")
+            .with_note("arguments are matched by position".to_string());
+
+        let expected = "\
+error[E0104]: function 'add' takes 2 argument(s) but 1 were supplied
+ --> test.fe:5:11
+  |
+1 | func add(a, b) [
+  |      --- 'add' defined here
+5 |     print add(1);
+  |           ^^^^^^ called with 1 argument(s)
+  = note: arguments are matched by position
+";
+        assert_eq!(diagnostic.render("test.fe", source), expected);
+    }
+
+    #[test]
+    fn it_renders_json_on_a_single_line() {
+        let source = "let s = \"a\";\nfunc main() [\n    print x;\n]\n";
+        let var = Span { start: 37, end: 38, line: 3, col: 11 };
+        let diagnostic = Diagnostic::error("E0103", "cannot find variable 'x' in this scope".to_string())
+            .with_primary(var, "not declared \"here\"");
+
+        let expected = concat!(
+            r#"{"file":"dir\\test.fe","line":3,"column":11,"end_line":3,"end_column":12,"severity":"error","code":"E0103","#,
+            r#""message":"cannot find variable 'x' in this scope","#,
+            r#""labels":[{"line":3,"column":11,"end_line":3,"end_column":12,"primary":true,"label":"not declared \"here\""}],"notes":[],"help":[]}"#);
+        assert_eq!(diagnostic.to_json("dir\\test.fe", source), expected);
+    }
+
+    #[test]
+    fn it_renders_diagnostics_without_labels() {
+        let diagnostic = Diagnostic::error("E0502", "cannot find function 'main'".to_string());
+        assert_eq!(diagnostic.render("test.fe", ""), "error[E0502]: cannot find function 'main'\n");
+    }
+}
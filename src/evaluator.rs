@@ -1,253 +1,465 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::fmt::Display;
 use std::ops::Deref;
 use std::rc::Rc;
 
+use crate::context::Context;
+use crate::diagnostic::Diagnostic;
 use crate::executor::Executor;
 use crate::frame::Frame;
 use crate::logger::Logger;
-use crate::tree::ExprNode;
+use crate::runtime_error::{RuntimeError, RuntimeResult};
+use crate::tree::{ExprNode, Slot};
 use crate::value::Value;
 
-#[derive(Debug, Clone)]
-enum ArithmeticOp {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticOp {
     Add,
     Sub,
     Mul,
     Div,
 }
 
-#[derive(Debug, Clone)]
-enum RelationalOp {
-    Equal,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelationalOp {
     LessThan,
     GreaterThan,
-    NotEqual,
     LessThanEqual,
     GreaterThanEqual,
 }
 
+impl Display for ArithmeticOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArithmeticOp::Add => write!(f, "+"),
+            ArithmeticOp::Sub => write!(f, "-"),
+            ArithmeticOp::Mul => write!(f, "*"),
+            ArithmeticOp::Div => write!(f, "/"),
+        }
+    }
+}
+
+impl Display for RelationalOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RelationalOp::LessThan => write!(f, "<"),
+            RelationalOp::GreaterThan => write!(f, ">"),
+            RelationalOp::LessThanEqual => write!(f, "<="),
+            RelationalOp::GreaterThanEqual => write!(f, ">="),
+        }
+    }
+}
+
+/// What int32 arithmetic does when the exact result does not fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Wraps around in two's complement, `2147483647 + 1` is `-2147483648`.
+    Wrap,
+    /// Clamps to the nearest bound, `2147483647 + 1` is `2147483647`.
+    Saturate,
+    /// Stops the program with a runtime error.
+    #[default]
+    Error,
+}
+
+impl Overflow {
+    // result of the operation, none if it overflows and overflows are errors,
+    // the divisor is never zero
+    fn int32(self, op: &ArithmeticOp, a: i32, b: i32) -> Option<i32> {
+        match self {
+            Overflow::Wrap => Some(match op {
+                ArithmeticOp::Add => a.wrapping_add(b),
+                ArithmeticOp::Sub => a.wrapping_sub(b),
+                ArithmeticOp::Mul => a.wrapping_mul(b),
+                ArithmeticOp::Div => a.wrapping_div(b),
+            }),
+            Overflow::Saturate => Some(match op {
+                ArithmeticOp::Add => a.saturating_add(b),
+                ArithmeticOp::Sub => a.saturating_sub(b),
+                ArithmeticOp::Mul => a.saturating_mul(b),
+                ArithmeticOp::Div => a.saturating_div(b),
+            }),
+            Overflow::Error => match op {
+                ArithmeticOp::Add => a.checked_add(b),
+                ArithmeticOp::Sub => a.checked_sub(b),
+                ArithmeticOp::Mul => a.checked_mul(b),
+                ArithmeticOp::Div => a.checked_div(b),
+            },
+        }
+    }
+}
+
 pub struct Evaluator {}
 
 impl Evaluator {
-    pub fn evaluate(expr: Rc<ExprNode>, rc_frame: Rc<RefCell<Frame>>) -> Value {
+    /// Evaluates the expression, a runtime error is located at the innermost expression with a span.
+    pub fn evaluate(expr: Rc<ExprNode>, rc_frame: Rc<RefCell<Frame>>, context: &Context) -> RuntimeResult<Value> {
+        let span = expr.span();
+        Self::evaluate_expr(expr, rc_frame, context).map_err(|error| match span {
+            Some(span) => error.at(span),
+            None => error,
+        })
+    }
+
+    /// Value in the slot the analyzer resolved a name to, nil for a name it could not resolve.
+    pub fn lookup(slot: &Cell<Option<Slot>>, rc_frame: &RefCell<Frame>) -> Value {
+        match slot.get() {
+            Some(slot) => rc_frame.borrow().lookup(slot),
+            None => Value::Nil,
+        }
+    }
+
+    fn evaluate_expr(expr: Rc<ExprNode>, rc_frame: Rc<RefCell<Frame>>, context: &Context) -> RuntimeResult<Value> {
+        context.step()?;
         match expr.deref() {
-            ExprNode::Var(name) => {
-                rc_frame.borrow().lookup(name)
+            ExprNode::Var(_, _, slot) => {
+                Ok(Self::lookup(slot, &rc_frame))
             }
             ExprNode::Val(value) => {
-                value.clone()
+                Ok(value.clone())
             }
             ExprNode::String(value) => {
-                Value::Chars(value.clone())
+                Ok(Value::Chars(value.clone()))
             }
             ExprNode::Add(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
-                Self::arithmetic(value_a, value_b, ArithmeticOp::Add)
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
+                Self::arithmetic(value_a, value_b, ArithmeticOp::Add, context.overflow)
             }
             ExprNode::Mul(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
-                Self::arithmetic(value_a, value_b, ArithmeticOp::Mul)
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
+                Self::arithmetic(value_a, value_b, ArithmeticOp::Mul, context.overflow)
             }
             ExprNode::Sub(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
-                Self::arithmetic(value_a, value_b, ArithmeticOp::Sub)
-            }
-            // todo ExprNode::Div(expr_a, expr_b) => {
-            //     let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-            //     let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
-            //     Self::arithmetic(value_a, value_b, ArithmeticOp::Div)
-            // }
-            ExprNode::Call(name, rc_exprs) => {
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
+                Self::arithmetic(value_a, value_b, ArithmeticOp::Sub, context.overflow)
+            }
+            ExprNode::Div(expr_a, expr_b) => {
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
+                Self::arithmetic(value_a, value_b, ArithmeticOp::Div, context.overflow)
+            }
+            ExprNode::Call(name, rc_exprs, span, slot) => {
                 Logger::debug(&format!("evaluating call '{name}'", name = name));
-                match rc_frame.borrow().lookup_global(name) {
-                    Value::Func(rc_func, argc) => {
-                        assert_eq!(argc, rc_exprs.len());
-
+                let callee = Self::lookup(slot, &rc_frame);
+                match callee {
+                    Value::Func(rc_func, _) => {
                         let mut arguments = vec![];
                         for rc_expr in rc_exprs {
-                            let arg = Self::evaluate(rc_expr.clone(), rc_frame.clone());
+                            let arg = Self::evaluate(rc_expr.clone(), rc_frame.clone(), context)?;
                             arguments.push(arg);
                         }
 
-                        if let Some(globals) = rc_frame.borrow().get_globals() {
-                            Executor::execute_function(rc_func, globals, arguments)
-                        } else {
-                            panic!("Can't find globals in current frame!");
-                        }
+                        // the global frame has no parent and is its own globals
+                        let globals = rc_frame.borrow().get_globals().unwrap_or_else(|| rc_frame.clone());
+                        Executor::execute_function(rc_func, globals, arguments, context)
                     }
-                    _ => {
-                        panic!("Can't find function '{name}' in globals!");
+                    value => {
+                        Err(RuntimeError::new(Self::not_a_function(name, &value).with_primary(*span, "called here")))
                     }
                 }
             }
             ExprNode::LessThan(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
                 Self::relational(value_a, value_b, RelationalOp::LessThan)
             }
             ExprNode::GreaterThan(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
                 Self::relational(value_a, value_b, RelationalOp::GreaterThan)
             }
             ExprNode::EqualTo(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
-                Value::Bool(value_a == value_b)
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
+                Ok(Value::Bool(Self::equal(&value_a, &value_b)))
             }
             ExprNode::LessThanEq(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
                 Self::relational(value_a, value_b, RelationalOp::LessThanEqual)
             }
             ExprNode::GreaterThanEq(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
                 Self::relational(value_a, value_b, RelationalOp::GreaterThanEqual)
             }
             ExprNode::NotEqualTo(expr_a, expr_b) => {
-                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone());
-                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone());
-                Self::relational(value_a, value_b, RelationalOp::NotEqual)
+                let value_a = Self::evaluate(expr_a.clone(), rc_frame.clone(), context)?;
+                let value_b = Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?;
+                Ok(Value::Bool(!Self::equal(&value_a, &value_b)))
             }
-        }
-    }
-
-    fn arithmetic(value_a: Value, value_b: Value, op: ArithmeticOp) -> Value {
-        match value_a {
-            Value::Nil => { panic!("Left operand of '{op:?}' is Nil!"); }
-            Value::Bool(_a) => { panic!("Left operand of '{op:?}' is Bool!"); }
-            Value::I32(a) => {
-                match value_b {
-                    Value::Nil => { panic!("Right operand of '{op:?}' is Nil!"); }
-                    Value::Bool(_b) => { panic!("Right operand of '{op:?}' is Bool!"); }
-                    Value::I32(b) => {
-                        match op {
-                            ArithmeticOp::Add => { Value::I32(a + b) }
-                            ArithmeticOp::Sub => { Value::I32(a - b) }
-                            ArithmeticOp::Mul => { Value::I32(a * b) }
-                            ArithmeticOp::Div => { Value::I32(a / b) }
-                        }
-                    }
-                    Value::F32(_) => { todo!() }
-                    Value::Chars(_) => { todo!() }
-                    Value::Func(_, _) => { panic!("Right operand of '{op:?}' is Func!"); }
+            // the right operand is only evaluated if the left one does not decide the result
+            ExprNode::And(expr_a, expr_b) => {
+                match Self::evaluate(expr_a.clone(), rc_frame.clone(), context)? {
+                    Value::Bool(false) => Ok(Value::Bool(false)),
+                    Value::Bool(true) => Self::logical(Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?, "and"),
+                    value => Self::logical(value, "and"),
                 }
             }
-            Value::F32(a) => {
-                match value_b {
-                    Value::Nil => { panic!("Right operand of '{op:?}' is Nil!"); }
-                    Value::Bool(_b) => { panic!("Right operand of '{op:?}' is Bool!"); }
-                    Value::I32(b) => {
-                        match op {
-                            ArithmeticOp::Add => { Value::F32(a + (b as f32)) }
-                            ArithmeticOp::Sub => { Value::F32(a - (b as f32)) }
-                            ArithmeticOp::Mul => { Value::F32(a * (b as f32)) }
-                            ArithmeticOp::Div => { Value::F32(a / (b as f32)) }
-                        }
-                    }
-                    Value::F32(b) => {
-                        match op {
-                            ArithmeticOp::Add => { Value::F32(a + b) }
-                            ArithmeticOp::Sub => { Value::F32(a - b) }
-                            ArithmeticOp::Mul => { Value::F32(a * b) }
-                            ArithmeticOp::Div => { Value::F32(a / b) }
-                        }
-                    }
-                    Value::Chars(b) => {
-                        match op {
-                            ArithmeticOp::Add => { Value::Chars(a.to_string() + &b) }
-                            _ => { panic!("Can't perform '{op:?}' on Chars!"); }
-                        }
-                    }
-                    Value::Func(_, _) => { panic!("Right operand of '{op:?}' is Func!"); }
+            ExprNode::Or(expr_a, expr_b) => {
+                match Self::evaluate(expr_a.clone(), rc_frame.clone(), context)? {
+                    Value::Bool(true) => Ok(Value::Bool(true)),
+                    Value::Bool(false) => Self::logical(Self::evaluate(expr_b.clone(), rc_frame.clone(), context)?, "or"),
+                    value => Self::logical(value, "or"),
                 }
             }
-            Value::Chars(a) => {
-                match value_b {
-                    Value::Nil => { panic!("Right operand of '{op:?}' is Nil!"); }
-                    Value::Bool(b) => {
-                        match op {
-                            ArithmeticOp::Add => { Value::Chars(format!("{}{}", a, b)) }
-                            _ => { panic!("Can't perform '{op:?}' on Chars!"); }
-                        }
-                    }
-                    Value::I32(b) => {
-                        match op {
-                            ArithmeticOp::Add => { Value::Chars(a + &b.to_string()) }
-                            _ => { panic!("Can't perform '{op:?}' on Chars!"); }
-                        }
-                    }
-                    Value::F32(_) => { todo!() }
-                    Value::Chars(b) => {
-                        match op {
-                            ArithmeticOp::Add => { Value::Chars(a + &b) }
-                            _ => { panic!("Can't perform '{op:?}' on Chars!"); }
-                        }
-                    }
-                    Value::Func(_, _) => { panic!("Right operand of '{op:?}' is Func!"); }
+            ExprNode::Not(expr) => {
+                match Self::evaluate(expr.clone(), rc_frame.clone(), context)? {
+                    Value::Bool(b) => Ok(Value::Bool(!b)),
+                    value => Self::logical(value, "not"),
                 }
             }
-            Value::Func(_, _) => { panic!("Left operand of '{op:?}' is Func!"); }
         }
     }
 
-    fn relational(value_a: Value, value_b: Value, op: RelationalOp) -> Value {
-        match value_a {
-            Value::Nil => { panic!("Left operand of '{op:?}' is Nil!"); }
-            Value::Bool(_a) => { panic!("Left operand of '{op:?}' is Bool!"); }
-            Value::I32(a) => {
-                match value_b {
-                    Value::Nil => { panic!("Right operand of '{op:?}' is Nil!"); }
-                    Value::Bool(_b) => { panic!("Right operand of '{op:?}' is Bool!"); }
-                    Value::I32(b) => {
-                        match op {
-                            RelationalOp::Equal => { Value::Bool(a == b) }
-                            RelationalOp::LessThan => { Value::Bool(a < b) }
-                            RelationalOp::GreaterThan => { Value::Bool(a > b) }
-                            RelationalOp::NotEqual => { Value::Bool(a != b) }
-                            RelationalOp::LessThanEqual => { Value::Bool(a <= b) }
-                            RelationalOp::GreaterThanEqual => { Value::Bool(a >= b) }
-                        }
-                    }
-                    Value::F32(_) => { todo!() }
-                    Value::Chars(_) => { todo!() }
-                    Value::Func(_, _) => { panic!("Right operand of '{op:?}' is Func!"); }
+    /// Applies a binary operator to two evaluated operands, with the operator spelled like
+    /// in `Type::binary`.
+    pub fn apply(op: &str, value_a: Value, value_b: Value, overflow: Overflow) -> RuntimeResult<Value> {
+        match op {
+            "+" => Self::arithmetic(value_a, value_b, ArithmeticOp::Add, overflow),
+            "-" => Self::arithmetic(value_a, value_b, ArithmeticOp::Sub, overflow),
+            "*" => Self::arithmetic(value_a, value_b, ArithmeticOp::Mul, overflow),
+            "/" => Self::arithmetic(value_a, value_b, ArithmeticOp::Div, overflow),
+            "==" => Ok(Value::Bool(Self::equal(&value_a, &value_b))),
+            "<" => Self::relational(value_a, value_b, RelationalOp::LessThan),
+            ">" => Self::relational(value_a, value_b, RelationalOp::GreaterThan),
+            "!=" => Ok(Value::Bool(!Self::equal(&value_a, &value_b))),
+            "<=" => Self::relational(value_a, value_b, RelationalOp::LessThanEqual),
+            ">=" => Self::relational(value_a, value_b, RelationalOp::GreaterThanEqual),
+            _ => panic!("Unknown operator '{op}'!"),
+        }
+    }
+
+    /// Error for calling a value that is not a function.
+    pub fn not_a_function(name: &str, value: &Value) -> Diagnostic {
+        Diagnostic::error("E0506", format!("'{name}' is not a function, found {}", value.type_name()))
+    }
+
+    /// The operand of a logical operator, which must be a bool.
+    pub fn logical(value: Value, op: &str) -> RuntimeResult<Value> {
+        match value {
+            Value::Bool(b) => Ok(Value::Bool(b)),
+            _ => Err(RuntimeError::new(Diagnostic::error("E0501", format!("operand of '{op}' must be bool, found {}", value.type_name()))).with_label("operand is not a bool")),
+        }
+    }
+
+    fn invalid_operands(op: impl Display, value_a: &Value, value_b: &Value) -> RuntimeResult<Value> {
+        Err(RuntimeError::new(Diagnostic::error("E0501", format!(
+            "cannot apply '{op}' to {} and {}", value_a.type_name(), value_b.type_name()))).with_label("invalid operands"))
+    }
+
+    pub fn arithmetic(value_a: Value, value_b: Value, op: ArithmeticOp, overflow: Overflow) -> RuntimeResult<Value> {
+        match (&value_a, &value_b) {
+            (Value::I32(a), Value::I32(b)) => {
+                if let (ArithmeticOp::Div, 0) = (&op, b) {
+                    return Err(RuntimeError::new(Diagnostic::error("E0505", format!("attempt to divide {a} by zero"))).with_label("division by zero"));
+                }
+                match overflow.int32(&op, *a, *b) {
+                    Some(result) => Ok(Value::I32(result)),
+                    None => Err(RuntimeError::new(
+                        Diagnostic::error("E0507", format!("attempt to compute '{a} {op} {b}', which overflows int32"))
+                            .with_help("run with '--overflow=wrap' or '--overflow=saturate' to define a result".to_string()))
+                        .with_label("arithmetic overflow")),
                 }
             }
-            Value::F32(a) => {
-                match value_b {
-                    Value::Nil => { panic!("Right operand of '{op:?}' is Nil!"); }
-                    Value::Bool(_b) => { panic!("Right operand of '{op:?}' is Bool!"); }
-                    Value::I32(b) => {
-                        match op {
-                            RelationalOp::Equal => { Value::Bool(a == (b as f32)) }
-                            RelationalOp::LessThan => { Value::Bool(a < (b as f32)) }
-                            RelationalOp::GreaterThan => { Value::Bool(a > (b as f32)) }
-                            RelationalOp::NotEqual => { Value::Bool(a != (b as f32)) }
-                            RelationalOp::LessThanEqual => { Value::Bool(a <= (b as f32)) }
-                            RelationalOp::GreaterThanEqual => { Value::Bool(a >= (b as f32)) }
-                        }
-                    }
-                    Value::F32(b) => {
-                        match op {
-                            RelationalOp::Equal => { Value::Bool(a == b) }
-                            RelationalOp::LessThan => { Value::Bool(a < b) }
-                            RelationalOp::GreaterThan => { Value::Bool(a > b) }
-                            RelationalOp::NotEqual => { Value::Bool(a != b) }
-                            RelationalOp::LessThanEqual => { Value::Bool(a <= b) }
-                            RelationalOp::GreaterThanEqual => { Value::Bool(a >= b) }
-                        }
-                    }
-                    Value::Chars(_) => { todo!() }
-                    Value::Func(_, _) => { panic!("Right operand of '{op:?}' is Func!"); }
+            // the int32 operand is promoted to flt32
+            (Value::I32(a), Value::F32(b)) => { Ok(Value::F32(Self::float(*a as f32, *b, op))) }
+            (Value::F32(a), Value::I32(b)) => { Ok(Value::F32(Self::float(*a, *b as f32, op))) }
+            (Value::F32(a), Value::F32(b)) => { Ok(Value::F32(Self::float(*a, *b, op))) }
+            // a string concatenates with another operand formatted like print does, in either order
+            (Value::Chars(_), Value::Bool(_) | Value::I32(_) | Value::F32(_) | Value::Chars(_))
+            | (Value::Bool(_) | Value::I32(_) | Value::F32(_), Value::Chars(_)) if matches!(op, ArithmeticOp::Add) => {
+                Ok(Value::Chars(value_a.format() + &value_b.format()))
+            }
+            _ => Self::invalid_operands(op, &value_a, &value_b),
+        }
+    }
+
+    fn float(a: f32, b: f32, op: ArithmeticOp) -> f32 {
+        match op {
+            ArithmeticOp::Add => { a + b }
+            ArithmeticOp::Sub => { a - b }
+            ArithmeticOp::Mul => { a * b }
+            ArithmeticOp::Div => { a / b }
+        }
+    }
+
+    // strings are ordered by their chars, which is the order of their unicode scalar values
+    pub fn relational(value_a: Value, value_b: Value, op: RelationalOp) -> RuntimeResult<Value> {
+        let ordering = match (&value_a, &value_b) {
+            (Value::I32(a), Value::I32(b)) => { a.partial_cmp(b) }
+            (Value::I32(a), Value::F32(b)) => { (*a as f32).partial_cmp(b) }
+            (Value::F32(a), Value::I32(b)) => { a.partial_cmp(&(*b as f32)) }
+            (Value::F32(a), Value::F32(b)) => { a.partial_cmp(b) }
+            (Value::Chars(a), Value::Chars(b)) => { a.chars().partial_cmp(b.chars()) }
+            _ => return Self::invalid_operands(op, &value_a, &value_b),
+        };
+        // nan is unordered, every comparison with it is false
+        Ok(Value::Bool(match ordering {
+            None => { false }
+            Some(ordering) => match op {
+                RelationalOp::LessThan => { ordering.is_lt() }
+                RelationalOp::GreaterThan => { ordering.is_gt() }
+                RelationalOp::LessThanEqual => { ordering.is_le() }
+                RelationalOp::GreaterThanEqual => { ordering.is_ge() }
+            },
+        }))
+    }
+
+    // equality is defined for all values: numbers are equal if their promoted values are,
+    // functions if they are the same function, values of different types never are
+    pub fn equal(value_a: &Value, value_b: &Value) -> bool {
+        match (value_a, value_b) {
+            (Value::I32(a), Value::F32(b)) => { (*a as f32) == *b }
+            (Value::F32(a), Value::I32(b)) => { *a == (*b as f32) }
+            _ => { value_a == value_b }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::span::Span;
+    use crate::tree::{BlockNode, FuncNode};
+    use crate::value::Value;
+
+    use super::{Evaluator, Overflow};
+
+    const OPS: [&str; 6] = ["+", "-", "*", "/", "<", ">="];
+
+    // result of an operation as expected in the table, flt32 in debug format to tell it from int32
+    fn show(op: &str, value_a: Value, value_b: Value) -> String {
+        match Evaluator::apply(op, value_a, value_b, Overflow::Error) {
+            Ok(Value::Bool(b)) => b.to_string(),
+            Ok(Value::I32(i)) => i.to_string(),
+            Ok(Value::F32(f)) => format!("{f:?}"),
+            Ok(Value::Chars(s)) => format!("{s:?}"),
+            Ok(value) => panic!("{op} evaluated to {value:?}"),
+            Err(_) => "err".to_string(),
+        }
+    }
+
+    #[test]
+    fn it_applies_operators_to_every_combination_of_values() {
+        let func = Rc::new(FuncNode::new("f".to_string(), vec![], None, BlockNode::new(), Span::default()));
+        let lefts = [Value::Nil, Value::Bool(true), Value::I32(7), Value::F32(2.5), Value::Chars("ab".to_string()), Value::Func(func.clone(), 0)];
+        let rights = [Value::Nil, Value::Bool(false), Value::I32(2), Value::F32(0.5), Value::Chars("cd".to_string()), Value::Func(func, 0)];
+        let err = ["err"; 6];
+
+        // results of + - * / < >= for every left operand in the rows and every right one in the columns
+        let table: [[[&str; 6]; 6]; 6] = [
+            // nil
+            [err, err, err, err, err, err],
+            // true
+            [err, err, err, err, ["\"truecd\"", "err", "err", "err", "err", "err"], err],
+            // 7
+            [
+                err,
+                err,
+                ["9", "5", "14", "3", "false", "true"],
+                ["7.5", "6.5", "3.5", "14.0", "false", "true"],
+                ["\"7cd\"", "err", "err", "err", "err", "err"],
+                err,
+            ],
+            // 2.5
+            [
+                err,
+                err,
+                ["4.5", "0.5", "5.0", "1.25", "false", "true"],
+                ["3.0", "2.0", "1.25", "5.0", "false", "true"],
+                ["\"2.5cd\"", "err", "err", "err", "err", "err"],
+                err,
+            ],
+            // "ab"
+            [
+                err,
+                ["\"abfalse\"", "err", "err", "err", "err", "err"],
+                ["\"ab2\"", "err", "err", "err", "err", "err"],
+                ["\"ab0.5\"", "err", "err", "err", "err", "err"],
+                ["\"abcd\"", "err", "err", "err", "true", "false"],
+                err,
+            ],
+            // func
+            [err, err, err, err, err, err],
+        ];
+
+        for (i, left) in lefts.iter().enumerate() {
+            for (j, right) in rights.iter().enumerate() {
+                for (k, op) in OPS.iter().enumerate() {
+                    assert_eq!(show(op, left.clone(), right.clone()), table[i][j][k], "{left:?} {op} {right:?}");
                 }
             }
-            Value::Chars(_) => { todo!() }
-            Value::Func(_, _) => { panic!("Left operand of '{op:?}' is Func!"); }
         }
     }
-}
\ No newline at end of file
+
+    fn int32(op: &str, a: i32, b: i32, overflow: Overflow) -> Result<i32, &'static str> {
+        match Evaluator::apply(op, Value::I32(a), Value::I32(b), overflow) {
+            Ok(Value::I32(result)) => Ok(result),
+            Ok(value) => panic!("int32 {op} int32 evaluated to {value:?}"),
+            Err(error) => Err(error.code()),
+        }
+    }
+
+    #[test]
+    fn it_compares_strings_by_unicode_scalar_values() {
+        let chars = |s: &str| Value::Chars(s.to_string());
+        assert_eq!(show("<", chars("apple"), chars("banana")), "true");
+        assert_eq!(show("<", chars("app"), chars("apple")), "true");
+        assert_eq!(show("<", chars("Zebra"), chars("apple")), "true");
+        assert_eq!(show(">", chars("é"), chars("z")), "true");
+        assert_eq!(show("<=", chars("same"), chars("same")), "true");
+        assert_eq!(show(">=", chars(""), chars("a")), "false");
+    }
+
+    #[test]
+    fn it_defines_equality_for_every_combination_of_values() {
+        let func = Rc::new(FuncNode::new("f".to_string(), vec![], None, BlockNode::new(), Span::default()));
+        let other = Rc::new(FuncNode::new("f".to_string(), vec![], None, BlockNode::new(), Span::default()));
+        let values = [
+            Value::Nil, Value::Bool(true), Value::I32(2), Value::F32(2.0), Value::Chars("2".to_string()),
+            Value::Func(func.clone(), 0), Value::Func(other, 0),
+        ];
+        // only numbers of different types can be equal, and a function only to itself
+        let equal = |i: usize, j: usize| i == j || (i, j) == (2, 3) || (i, j) == (3, 2);
+
+        for (i, value_a) in values.iter().enumerate() {
+            for (j, value_b) in values.iter().enumerate() {
+                let expected = equal(i, j);
+                assert_eq!(show("==", value_a.clone(), value_b.clone()), expected.to_string(), "{value_a:?} == {value_b:?}");
+                assert_eq!(show("!=", value_a.clone(), value_b.clone()), (!expected).to_string(), "{value_a:?} != {value_b:?}");
+            }
+        }
+        assert_eq!(show("==", Value::Func(func.clone(), 0), Value::Func(func, 0)), "true");
+        assert_eq!(show("==", Value::F32(f32::NAN), Value::F32(f32::NAN)), "false");
+    }
+
+    #[test]
+    fn it_handles_int32_overflow_as_configured() {
+        assert_eq!(int32("+", i32::MAX, 1, Overflow::Wrap), Ok(i32::MIN));
+        assert_eq!(int32("+", i32::MAX, 1, Overflow::Saturate), Ok(i32::MAX));
+        assert_eq!(int32("+", i32::MAX, 1, Overflow::Error), Err("E0507"));
+
+        assert_eq!(int32("-", i32::MIN, 1, Overflow::Wrap), Ok(i32::MAX));
+        assert_eq!(int32("-", i32::MIN, 1, Overflow::Saturate), Ok(i32::MIN));
+        assert_eq!(int32("*", 65536, -65536, Overflow::Saturate), Ok(i32::MIN));
+        assert_eq!(int32("*", 65536, 65536, Overflow::Error), Err("E0507"));
+
+        assert_eq!(int32("/", i32::MIN, -1, Overflow::Wrap), Ok(i32::MIN));
+        assert_eq!(int32("/", i32::MIN, -1, Overflow::Saturate), Ok(i32::MAX));
+        assert_eq!(int32("/", i32::MIN, -1, Overflow::Error), Err("E0507"));
+    }
+
+    #[test]
+    fn it_never_defines_division_by_zero() {
+        for overflow in [Overflow::Wrap, Overflow::Saturate, Overflow::Error] {
+            assert_eq!(int32("/", 7, 0, overflow), Err("E0505"));
+        }
+        assert_eq!(int32("*", 1000, 1000, Overflow::Error), Ok(1_000_000));
+    }
+}
@@ -1,159 +1,480 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::ops::Deref;
 use std::rc::Rc;
 
+use crate::context::Context;
+use crate::diagnostic::Diagnostic;
 use crate::evaluator::Evaluator;
 use crate::frame::Frame;
 use crate::logger::Logger;
-use crate::tree::{BlockNode, FuncNode, ProgramNode, StmtNode};
+use crate::runtime_error::{RuntimeError, RuntimeResult};
+use crate::span::Span;
+use crate::tree::{BlockNode, ExprNode, FuncNode, ProgramNode, Slot, StmtNode};
 use crate::value::Value;
 
 enum Control {
     Next,
     Return,
+    // return the result of calling the function with the arguments
+    TailCall(Rc<FuncNode>, Vec<Value>),
     Break,
     Continue,
 }
 
 pub struct Executor {
     program: Rc<ProgramNode>,
+    context: Context,
 }
 
 impl Executor {
-    pub fn new(program: Rc<ProgramNode>) -> Executor {
-        Executor { program }
+    pub fn new(program: Rc<ProgramNode>, context: Context) -> Executor {
+        Executor { program, context }
     }
 
-    pub fn execute(&self) {
+    /// Runs main, a runtime error stops the program and is returned with the calls it unwound.
+    pub fn execute(&self) -> RuntimeResult<()> {
         Logger::info("Execute.");
-        self.execute_program();
+        self.context.start();
+        self.execute_program()
     }
 
-    fn execute_program(&self) {
-        Logger::info("Execute Program.");
+    /// Calls a function of the program with the given arguments and returns its result.
+    pub fn call(&self, name: &str, arguments: Vec<Value>) -> RuntimeResult<Value> {
+        self.context.start();
+        let rc_global = Rc::new(RefCell::new(self.globals()));
+        let callee = Self::global_index(&self.program, name)
+            .map_or(Value::Nil, |index| rc_global.borrow().lookup(Slot { depth: 0, index }));
+        match callee {
+            Value::Func(rc_func, _) => Self::execute_function(rc_func, rc_global, arguments, &self.context),
+            value => Err(RuntimeError::new(Evaluator::not_a_function(name, &value))),
+        }
+    }
+
+    fn globals(&self) -> Frame {
+        let values = Self::global_values(&self.program);
+        let mut global = Frame::new(None, values.len());
+        for (index, value) in values.into_iter().enumerate() {
+            global.declare(index, value);
+        }
+        global
+    }
 
-        // get program node symbol table
-        let rc_symbols = self.program.symbols.clone();
-        let symbols = rc_symbols.borrow();
+    /// Initial values of the globals from the program nodes, the global variables followed by
+    /// the functions. The symbol table still refers to the functions as they were before optimization.
+    pub fn global_values(program: &ProgramNode) -> Vec<Value> {
+        program.let_nodes.iter()
+            .map(|rc_let| rc_let.value.clone())
+            .chain(program.func_nodes.iter().map(|rc_func| Value::Func(rc_func.clone(), rc_func.parameters.len())))
+            .collect()
+    }
 
-        // find main function node
-        let rc_main = if let Some(main) = symbols.map.get("main") {
-            match &main.value {
-                Value::Func(rc_main, _) => { rc_main.clone() }
-                _ => { panic!("Symbol 'main' is not a function!"); }
+    /// Index of a global among the global values, in the order the analyzer resolves them.
+    pub fn global_index(program: &ProgramNode, name: &str) -> Option<usize> {
+        program.let_nodes.iter().map(|rc_let| &rc_let.name)
+            .chain(program.func_nodes.iter().map(|rc_func| &rc_func.name))
+            .position(|global| global == name)
+    }
+
+    /// The function execution starts with, given the value of the global named main.
+    pub fn main_function(program: &ProgramNode, main: Value) -> RuntimeResult<Rc<FuncNode>> {
+        match main {
+            Value::Func(rc_main, _) => Ok(rc_main),
+            Value::Nil => {
+                Err(RuntimeError::new(Diagnostic::error("E0502", "cannot find function 'main'".to_string())
+                    .with_note("execution starts by calling 'main'".to_string())))
             }
-        } else {
-            panic!("Cannot find 'main' symbol!");
-        };
+            _ => {
+                let span = program.let_nodes.iter()
+                    .find(|rc_let| rc_let.name == "main")
+                    .map(|rc_let| rc_let.span)
+                    .unwrap_or_default();
+                Err(RuntimeError::new(Diagnostic::error("E0502", "'main' is not a function".to_string())
+                    .with_primary(span, "declared as a global variable")))
+            }
+        }
+    }
 
-        // create global stack frame
-        let mut global = Frame::new(None);
-        global.init_symbols(symbols.deref());
+    fn execute_program(&self) -> RuntimeResult<()> {
+        Logger::info("Execute Program.");
+
+        let global = self.globals();
+
+        // find main function node
+        let main = Self::global_index(&self.program, "main")
+            .map_or(Value::Nil, |index| global.lookup(Slot { depth: 0, index }));
+        let rc_main = Self::main_function(&self.program, main)?;
         let rc_global = Rc::new(RefCell::new(global));
 
         // execute main function
         // todo: could probably actually accept arguments here to pass to main
         let arguments = vec![];
-        Self::execute_function(rc_main, rc_global, arguments);
+        Self::execute_function(rc_main, rc_global, arguments, &self.context)?;
 
         Logger::info("Program finished.");
+        Ok(())
+    }
+
+    pub fn execute_function(mut rc_func: Rc<FuncNode>, globals: Rc<RefCell<Frame>>, mut arguments: Vec<Value>, context: &Context) -> RuntimeResult<Value> {
+        if !context.enter() {
+            return Err(Self::call_depth_exceeded(&rc_func, context));
+        }
+
+        // a tail call replaces the running function instead of nesting in it,
+        // so that tail recursion runs in a loop on the same native stack
+        let result = loop {
+            match Self::execute_body(rc_func.clone(), globals.clone(), arguments, context) {
+                Ok((Control::TailCall(rc_callee, callee_arguments), _)) => {
+                    rc_func = rc_callee;
+                    arguments = callee_arguments;
+                }
+                Ok((_, value)) => break Ok(value),
+                Err(error) => break Err(error),
+            }
+        };
+        context.leave();
+        // the analyzer only marks tail calls to functions with the same return type
+        result.and_then(|value| Self::check_result(&rc_func, value))
     }
 
-    pub fn execute_function(rc_func: Rc<FuncNode>, globals: Rc<RefCell<Frame>>, arguments: Vec<Value>) -> Value {
+    fn execute_body(rc_func: Rc<FuncNode>, globals: Rc<RefCell<Frame>>, arguments: Vec<Value>, context: &Context) -> RuntimeResult<(Control, Value)> {
         let name = &rc_func.name;
         Logger::debug(&format!("calling function '{name}'.", name = name));
 
         // create local stack frame
-        let mut locals = Frame::new(Some(globals));
+        let mut locals = Frame::new(Some(globals), rc_func.block_node.slots.get());
 
         // initialize parameters
-        let name = &rc_func.name;
-        if rc_func.numParameters() > arguments.len() {
-            panic!("Not enough arguments for function {name}!");
-        }
-        if rc_func.numParameters() < arguments.len() {
-            panic!("To many arguments for function {name}!");
-        }
-        locals.init_parameters(&rc_func.parameters, arguments);
+        Self::check_arguments(&rc_func, &arguments)?;
+        locals.init_parameters(arguments);
 
         // execute function block
         let rc_block = rc_func.block_node.clone();
         let rc_locals = Rc::new(RefCell::new(locals));
-        let (_, value) = Self::execute_block_without_scope(rc_block, rc_locals);
+        Self::execute_block_without_scope(rc_block, rc_locals, context)
+            .map_err(|error| error.leave(name, rc_func.span))
+    }
+
+    /// Error for a call that would exceed the maximum call depth, located at the call.
+    pub fn call_depth_exceeded(rc_func: &FuncNode, context: &Context) -> RuntimeError {
+        let name = &rc_func.name;
+        let diagnostic = if context.depth() < context.max_call_depth {
+            Diagnostic::error("E0508", format!(
+                "stack of the interpreter exhausted after {depth} calls when calling '{name}'", depth = context.depth()))
+                .with_note("blocks and expressions nested in a function take stack in every call".to_string())
+                .with_help("recurse less deeply, or nest less deeply in the recursive function".to_string())
+        } else {
+            Diagnostic::error("E0508", format!(
+                "maximum call depth of {max} exceeded when calling '{name}'", max = context.max_call_depth))
+                .with_help("use '--max-call-depth' to allow deeper recursion".to_string())
+        };
+        RuntimeError::new(diagnostic).with_label("call depth exceeded here")
+    }
+
+    /// Error for an if or while statement, named by its keyword, whose condition is not a bool.
+    pub fn condition_not_bool(keyword: &str, condition: &Value, span: Span) -> RuntimeError {
+        RuntimeError::new(
+            Diagnostic::error("E0504", format!("condition of '{keyword}' must be bool, found {}", condition.type_name()))
+                .with_primary(span, "condition is not a bool"))
+    }
+
+    // errors before the function is entered are located at its call
+    pub fn check_arguments(rc_func: &FuncNode, arguments: &[Value]) -> RuntimeResult<()> {
+        let name = &rc_func.name;
+        if rc_func.numParameters() != arguments.len() {
+            return Err(RuntimeError::new(
+                Diagnostic::error("E0503", format!(
+                    "function '{name}' takes {expected} argument(s) but {found} were supplied",
+                    expected = rc_func.numParameters(), found = arguments.len()))
+                    .with_secondary(rc_func.span, &format!("'{name}' defined here")))
+                .with_label("wrong number of arguments"));
+        }
+        // annotations are checked at runtime too, unannotated code may pass any value
+        for (param, argument) in rc_func.parameters.iter().zip(arguments) {
+            if let Some(expected) = param.param_type.filter(|expected| !expected.holds(argument)) {
+                return Err(RuntimeError::new(
+                    Diagnostic::error("E0512", format!(
+                        "argument '{param}' of '{name}' expects {expected}, found {found}", param = param.name, found = argument.type_name()))
+                        .with_help("annotated parameters only accept values of their type".to_string()))
+                    .with_label("argument of the wrong type"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The result of a function if it has the annotated return type, an error located at the call otherwise.
+    pub fn check_result(rc_func: &FuncNode, value: Value) -> RuntimeResult<Value> {
+        match rc_func.return_type {
+            Some(expected) if !expected.holds(&value) => Err(RuntimeError::new(
+                Diagnostic::error("E0512", format!(
+                    "function '{name}' returns {expected}, found {found}", name = rc_func.name, found = value.type_name()))
+                    .with_help("annotated functions only return values of their type".to_string()))
+                .with_label("result of the wrong type")),
+            _ => Ok(value),
+        }
+    }
+
+    // evaluates the arguments of a call marked as tail call by the analyzer,
+    // none if the callee turns out not to be a function
+    fn execute_tail_call(name: &String, rc_exprs: &[Rc<ExprNode>], span: Span, slot: &Cell<Option<Slot>>, rc_locals: Rc<RefCell<Frame>>, context: &Context) -> RuntimeResult<Option<Control>> {
+        let callee = Evaluator::lookup(slot, &rc_locals);
+        let Value::Func(rc_callee, _) = callee else {
+            return Ok(None);
+        };
+        Logger::debug(&format!("evaluating tail call '{name}'", name = name));
+        context.step().map_err(|error| error.at(span))?;
+
+        let mut arguments = vec![];
+        for rc_expr in rc_exprs {
+            arguments.push(Evaluator::evaluate(rc_expr.clone(), rc_locals.clone(), context)?);
+        }
+        Self::check_arguments(&rc_callee, &arguments).map_err(|error| error.at(span))?;
+        Ok(Some(Control::TailCall(rc_callee, arguments)))
+    }
 
-        value
+    fn execute_block_with_scope(rc_block: Rc<BlockNode>, rc_enclosing: Rc<RefCell<Frame>>, context: &Context) -> RuntimeResult<(Control, Value)> {
+        // nested blocks get their own frame for their let statements
+        let rc_locals = Rc::new(RefCell::new(Frame::new(Some(rc_enclosing), rc_block.slots.get())));
+        Self::execute_block_without_scope(rc_block, rc_locals, context)
     }
 
-    fn execute_block_without_scope(rc_block: Rc<BlockNode>, rc_locals: Rc<RefCell<Frame>>) -> (Control, Value) {
-        // execute statements
+    fn execute_block_without_scope(rc_block: Rc<BlockNode>, rc_locals: Rc<RefCell<Frame>>, context: &Context) -> RuntimeResult<(Control, Value)> {
+        // execute statements, errors without a location of their own are located at their statement
         for statement in &rc_block.statements {
             let (control, value) = Self::execute_statement(
                 statement.clone(),
                 rc_locals.clone(),
-            );
+                context,
+            ).map_err(|error| error.at(statement.span()))?;
             match control {
                 Control::Next => {}
-                Control::Return => { return (Control::Return, value); }
+                Control::Return | Control::TailCall(_, _) => { return Ok((control, value)); }
                 Control::Break => {}
                 Control::Continue => {}
             }
         }
 
-        (Control::Next, Value::Nil)
+        Ok((Control::Next, Value::Nil))
     }
 
     fn execute_statement(
         rc_statement: Rc<StmtNode>,
         rc_locals: Rc<RefCell<Frame>>,
-    ) -> (Control, Value)
+        context: &Context,
+    ) -> RuntimeResult<(Control, Value)>
     {
+        context.step()?;
         match rc_statement.deref() {
-            StmtNode::Let(_) => {
-                Logger::debug("ignoring let statement");
-                (Control::Next, Value::Nil)
+            StmtNode::Let(let_node) => {
+                Logger::debug("executing let statement");
+                let slot = let_node.slot.get().expect("let statement resolved by the analyzer");
+                rc_locals.borrow_mut().declare(slot.index, let_node.value.clone());
+                Ok((Control::Next, Value::Nil))
             }
             StmtNode::Assign(assign) => {
                 Logger::debug("executing assign statement");
-                let name = &assign.name;
-                let value = Evaluator::evaluate(assign.expr.clone(), rc_locals.clone());
-                rc_locals.borrow_mut().assign(name, value);
-                (Control::Next, Value::Nil)
+                let slot = assign.slot.get().expect("assignment resolved by the analyzer");
+                let value = Evaluator::evaluate(assign.expr.clone(), rc_locals.clone(), context)?;
+                rc_locals.borrow_mut().assign(slot, value);
+                Ok((Control::Next, Value::Nil))
             }
             StmtNode::Return(ret) => {
                 Logger::debug("executing return statement");
-                let value = Evaluator::evaluate(ret.expr.clone(), rc_locals.clone());
-                (Control::Return, value)
+                if let (true, ExprNode::Call(name, rc_exprs, span, slot)) = (ret.tail_call.get(), ret.expr.deref()) {
+                    if let Some(control) = Self::execute_tail_call(name, rc_exprs, *span, slot, rc_locals.clone(), context)? {
+                        return Ok((control, Value::Nil));
+                    }
+                }
+                let value = Evaluator::evaluate(ret.expr.clone(), rc_locals.clone(), context)?;
+                Ok((Control::Return, value))
             }
             StmtNode::Print(print) => {
                 Logger::debug("executing print statement");
-                let value = Evaluator::evaluate(print.expr.clone(), rc_locals.clone());
-                value.print();
-                (Control::Next, Value::Nil)
+                let value = Evaluator::evaluate(print.expr.clone(), rc_locals.clone(), context)?;
+                context.print(&value);
+                Ok((Control::Next, Value::Nil))
             }
             StmtNode::While(while_node) => {
                 Logger::debug("executing while statement");
-                while Evaluator::evaluate(while_node.condition.clone(), rc_locals.clone()) == Value::Bool(true) {
-                    Self::execute_block_without_scope(while_node.body.clone(), rc_locals.clone());
+                loop {
+                    match Evaluator::evaluate(while_node.condition.clone(), rc_locals.clone(), context)? {
+                        Value::Bool(true) => {}
+                        Value::Bool(false) => return Ok((Control::Next, Value::Nil)),
+                        condition => return Err(Self::condition_not_bool("while", &condition, while_node.span)),
+                    }
+                    let (control, value) = Self::execute_block_with_scope(while_node.body.clone(), rc_locals.clone(), context)?;
+                    if let Control::Return | Control::TailCall(_, _) = control {
+                        return Ok((control, value));
+                    }
                 }
-                (Control::Next, Value::Nil)
             }
             StmtNode::IfElse(if_else_node) => {
                 Logger::debug("executing if else statement");
-                let condition = Evaluator::evaluate(if_else_node.condition.clone(), rc_locals.clone());
+                let condition = Evaluator::evaluate(if_else_node.condition.clone(), rc_locals.clone(), context)?;
                 if let Value::Bool(b) = condition {
                     if b {
                         Logger::debug("executing if body");
-                        return Self::execute_block_without_scope(if_else_node.ifBody.clone(), rc_locals.clone());
+                        return Self::execute_block_with_scope(if_else_node.ifBody.clone(), rc_locals.clone(), context);
                     }
                     if !b && if_else_node.elseBody.is_some() {
                         Logger::debug("executing else body");
-                        return Self::execute_block_without_scope(if_else_node.elseBody.clone().unwrap(), rc_locals.clone());
+                        return Self::execute_block_with_scope(if_else_node.elseBody.clone().unwrap(), rc_locals.clone(), context);
                     }
-                    (Control::Next, Value::Nil)
+                    Ok((Control::Next, Value::Nil))
                 } else {
-                    panic!("If-then-else statement condition must be of type boolean!");
+                    Err(Self::condition_not_bool("if", &condition, if_else_node.span))
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::analyzer::Analyzer;
+    use crate::context::Context;
+    use crate::diagnostic::Diagnostics;
+    use crate::lexer::Lexer;
+    use crate::parser::DescentParser;
+    use crate::runtime_error::RuntimeResult;
+    use crate::value::Value;
+
+    use super::Executor;
+
+    const SOURCE: &str = "
+        let total = 0;
+        func shadow(n) [
+            let x = 1;
+            if n > 0 [
+                let x = 2;
+                total = total + x;
+            ]
+            return x + total;
+        ]
+        func sum_to(n, acc) [
+            if n == 0 [
+                return acc;
+            ]
+            return sum_to(n - 1, acc + n);
+        ]
+        func is_even(n) [
+            if n == 0 [
+                return true;
+            ]
+            return is_odd(n - 1);
+        ]
+        func is_odd(n) [
+            if n == 0 [
+                return false;
+            ]
+            return is_even(n - 1);
+        ]
+        func count(n, acc) [
+            if n == 0 [
+                return acc;
+            ]
+            return count(n - 1, acc + 1);
+        ]
+        func sum_to_nested(n) [
+            if n == 0 [
+                return 0;
+            ]
+            return n + sum_to_nested(n - 1);
+        ]
+        func add(a, b) [
+            return a + b;
+        ]
+        func same(a, b) [
+            return a == b;
+        ]
+        func mixed() [
+            let s;
+            s = add(1, 2);
+            s = add(\"a\", \"b\");
+            if same(1, \"x\") [
+                return s;
+            ]
+            return add(\"n=\", add(1, 2.5));
+        ]
+        func inc(x) [
+            return x + 1;
+        ]
+        func half(n) [
+            return n / 2;
+        ]
+        func numbers() [
+            let f = 3.0;
+            return inc(1) + inc(1.5) + half(4) + half(f);
+        ]
+        func main() [
+        ]
+    ";
+
+    fn executor(source: &str, context: Context) -> Executor {
+        let diagnostics = Rc::new(Diagnostics::new("<test>", source));
+        let mut parser = DescentParser::new(Lexer::new(source.to_string()), diagnostics.clone());
+        let program = Rc::new(parser.analyze());
+        Analyzer::new(program.clone(), diagnostics).analyze().unwrap();
+        Executor::new(program, context)
+    }
+
+    // calls a function of the analyzed source with a call depth limit of 10
+    fn call(name: &str, arguments: Vec<Value>) -> RuntimeResult<Value> {
+        let mut context = Context::new();
+        context.max_call_depth = 10;
+        executor(SOURCE, context).call(name, arguments)
+    }
+
+    #[test]
+    fn it_finds_variables_in_their_slots() {
+        assert_eq!(call("shadow", vec![Value::I32(0)]).ok(), Some(Value::I32(1)));
+        assert_eq!(call("shadow", vec![Value::I32(1)]).ok(), Some(Value::I32(3)));
+    }
+
+    #[test]
+    fn it_runs_unannotated_functions_with_mixed_operands() {
+        assert_eq!(call("mixed", vec![]).ok(), Some(Value::Chars("n=3.5".to_string())));
+    }
+
+    #[test]
+    fn it_calls_inferred_functions_with_int_and_float_arguments() {
+        assert_eq!(call("numbers", vec![]).ok(), Some(Value::F32(8.0)));
+    }
+
+    #[test]
+    fn it_runs_tail_recursion_in_constant_stack_space() {
+        let result = call("sum_to", vec![Value::I32(65_000), Value::I32(0)]);
+        assert_eq!(result.ok(), Some(Value::I32(2_112_532_500)));
+    }
+
+    // takes seconds in unoptimized builds, run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn it_runs_millions_of_tail_calls() {
+        assert_eq!(call("count", vec![Value::I32(3_000_000), Value::I32(0)]).ok(), Some(Value::I32(3_000_000)));
+    }
+
+    #[test]
+    fn it_runs_mutual_tail_recursion_in_constant_stack_space() {
+        assert_eq!(call("is_even", vec![Value::I32(100_001)]).ok(), Some(Value::Bool(false)));
+        assert_eq!(call("is_odd", vec![Value::I32(100_001)]).ok(), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn it_counts_calls_that_are_not_in_tail_position() {
+        assert_eq!(call("sum_to_nested", vec![Value::I32(5)]).ok(), Some(Value::I32(15)));
+        assert_eq!(call("sum_to_nested", vec![Value::I32(10)]).map_err(|error| error.code()), Err("E0508"));
+    }
+
+    #[test]
+    fn it_stops_deeply_nested_recursion_before_the_stack_overflows() {
+        // the default call depth of 40 nested ifs takes far more than the 8 MiB of stack
+        let result = std::thread::Builder::new().stack_size(16 * 1024 * 1024).spawn(|| {
+            let mut context = Context::new();
+            context.stack_size = Some(8 * 1024 * 1024);
+            executor(include_str!("../test_files/nested_recursion.fe"), context).call("descend", vec![Value::I32(0)])
+                .err().map(|error| error.code())
+        }).unwrap().join().unwrap();
+        assert_eq!(result, Some("E0508"));
+    }
+}
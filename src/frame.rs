@@ -1,70 +1,77 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::logger::Logger;
-use crate::symbols::Symbols;
-use crate::tree::Parameter;
+use crate::tree::Slot;
 use crate::value::Value;
 
+// variables are found by the slots the analyzer resolved their names to,
+// so a frame holds their values only, in the order of their slots
 pub struct Frame {
-    globals: Option<Rc<RefCell<Frame>>>,
-    values: HashMap<String, Value>,
+    // enclosing block frame, or the global frame for a function frame
+    parent: Option<Rc<RefCell<Frame>>>,
+    values: Vec<Value>,
 }
 
 impl Frame {
-    pub fn new(global: Option<Rc<RefCell<Frame>>>) -> Frame {
+    /// A frame with the given number of slots, all nil until declared.
+    pub fn new(parent: Option<Rc<RefCell<Frame>>>, size: usize) -> Frame {
         Frame {
-            globals: global,
-            values: HashMap::new(),
+            parent,
+            values: vec![Value::Nil; size],
         }
     }
 
     pub fn get_globals(&self) -> Option<Rc<RefCell<Frame>>> {
-        self.globals.clone()
-    }
-
-    pub fn init_symbols(&mut self, symbols: &Symbols) {
-        for (name, symbol) in &symbols.map {
-            self.values.insert(name.clone(), symbol.value.clone());
+        match &self.parent {
+            None => None,
+            Some(rc_parent) => {
+                match rc_parent.borrow().get_globals() {
+                    None => Some(rc_parent.clone()),
+                    Some(rc_globals) => Some(rc_globals),
+                }
+            }
         }
     }
 
-    pub fn init_parameters(&mut self, parameters: &Vec<Parameter>, arguments: Vec<Value>) {
-        assert_eq!(parameters.len(), arguments.len());
-
-        let mut iter_args = arguments.into_iter();
+    // the parameters take the first slots of a function frame, its let statements the rest
+    pub fn init_parameters(&mut self, arguments: Vec<Value>) {
+        let size = self.values.len();
+        assert!(arguments.len() <= size);
 
-        for rc_param in parameters {
-            let name = rc_param.name.clone();
-            let arg = iter_args.next().unwrap();
-            self.values.insert(name, arg);
-        }
+        self.values = arguments;
+        self.values.resize(size, Value::Nil);
     }
 
-    pub fn assign(&mut self, name: &String, value: Value) {
-        self.values.insert(name.clone(), value);
+    pub fn declare(&mut self, index: usize, value: Value) {
+        self.values[index] = value;
     }
 
-    pub fn lookup(&self, name: &String) -> Value {
-        match self.values.get(name) {
-            None => { Value::Nil }
-            Some(value) => { value.clone() }
+    // writes to the frame the given number of frames up
+    pub fn assign(&mut self, slot: Slot, value: Value) {
+        match slot.depth {
+            0 => self.values[slot.index] = value,
+            depth => self.parent.as_ref()
+                .expect("slot beyond the global frame")
+                .borrow_mut()
+                .assign(Slot { depth: depth - 1, index: slot.index }, value),
         }
     }
 
-    pub fn lookup_global(&self, name: &String) -> Value {
-        match &self.globals {
-            None => { Value::Nil }
-            Some(rc_globals) => {
-                rc_globals.borrow().lookup(name)
-            }
+    // reads from the frame the given number of frames up
+    pub fn lookup(&self, slot: Slot) -> Value {
+        match slot.depth {
+            0 => self.values[slot.index].clone(),
+            depth => self.parent.as_ref()
+                .expect("slot beyond the global frame")
+                .borrow()
+                .lookup(Slot { depth: depth - 1, index: slot.index }),
         }
     }
 
     pub fn print(&self) {
-        for (name, value) in &self.values {
-            Logger::debug(&format!("    {name} = {value:?}", name = name, value = value));
+        for (index, value) in self.values.iter().enumerate() {
+            Logger::debug(&format!("    [{index}] = {value:?}", index = index, value = value));
         }
     }
-}
\ No newline at end of file
+}
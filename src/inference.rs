@@ -0,0 +1,605 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::tree::{BlockNode, ExprNode, FuncNode, LetNode, ProgramNode, StmtNode};
+use crate::types::Type;
+
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub ret: Type,
+}
+
+impl Signature {
+    pub fn format(&self, func: &FuncNode) -> String {
+        let params: Vec<String> = func.parameters.iter()
+            .zip(&self.params)
+            .map(|(param, t)| format!("{}: {}", param.name, t))
+            .collect();
+        format!("func {}({}) -> {}", func.name, params.join(", "), self.ret)
+    }
+}
+
+/// Types inferred for every function, global and `let` of a program.
+/// Anything the inference could not pin down is reported as `Type::Dynamic`.
+#[derive(Debug, Default)]
+pub struct TypeTable {
+    pub signatures: HashMap<String, Signature>,
+    pub globals: HashMap<String, Type>,
+    lets: HashMap<*const LetNode, Type>,
+    exprs: HashMap<*const ExprNode, Type>,
+}
+
+impl TypeTable {
+    pub fn expr_type(&self, expr: &ExprNode) -> Type {
+        match self.exprs.get(&(expr as *const ExprNode)) {
+            Some(t) => *t,
+            None => Type::Dynamic,
+        }
+    }
+
+    pub fn set_expr_type(&mut self, expr: &ExprNode, t: Type) {
+        self.exprs.insert(expr as *const ExprNode, t);
+    }
+
+    pub fn let_type(&self, let_node: &LetNode) -> Type {
+        match self.lets.get(&(let_node as *const LetNode)) {
+            Some(t) => *t,
+            None => Type::Dynamic,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Term {
+    Con(Type),
+    Var(usize),
+}
+
+struct FuncTerms {
+    params: Vec<Term>,
+    ret: Term,
+    generalized: HashSet<usize>,
+}
+
+/// Hindley-Milner style inference over the whole program. Functions are inferred callees first,
+/// one strongly connected component of the call graph at a time, and generalized afterwards, so a
+/// function like `add(a, b)` gets the signature `('a, 'b) -> 'c` and can be called with any types.
+/// Conflicting constraints are not reported here: the first one wins and the type checker reports
+/// the rest against the inferred types, except for an unannotated `let` assigned values of different
+/// types, which is widened to dynamic.
+pub struct Inference<'a> {
+    program: &'a ProgramNode,
+    bindings: Vec<Option<Term>>,
+    funcs: HashMap<String, FuncTerms>,
+    globals: HashMap<String, Term>,
+    lets: Vec<(*const LetNode, Term)>,
+    // type variables of unannotated lets, widened to dynamic by a conflicting assignment
+    unannotated: HashSet<usize>,
+}
+
+impl<'a> Inference<'a> {
+    pub fn infer(program: &'a ProgramNode) -> TypeTable {
+        let mut inference = Inference {
+            program,
+            bindings: vec![],
+            funcs: HashMap::new(),
+            globals: HashMap::new(),
+            lets: vec![],
+            unannotated: HashSet::new(),
+        };
+        inference.infer_program();
+        inference.table()
+    }
+
+    fn infer_program(&mut self) {
+        for rc_let in &self.program.let_nodes {
+            let term = self.declare_let(rc_let);
+            self.globals.insert(rc_let.name.clone(), term);
+        }
+
+        for rc_func in &self.program.func_nodes {
+            let params = rc_func.parameters.iter()
+                .map(|param| self.annotated(param.param_type))
+                .collect();
+            let ret = self.annotated(rc_func.return_type);
+            self.funcs.insert(rc_func.name.clone(), FuncTerms { params, ret, generalized: HashSet::new() });
+        }
+
+        for component in self.call_graph_components() {
+            for rc_func in &component {
+                self.infer_function(rc_func, &component);
+            }
+            self.generalize(&component);
+        }
+    }
+
+    fn infer_function(&mut self, rc_func: &Rc<FuncNode>, component: &[Rc<FuncNode>]) {
+        let (params, ret) = {
+            let terms = &self.funcs[&rc_func.name];
+            (terms.params.clone(), terms.ret)
+        };
+
+        let mut scopes = vec![HashMap::new()];
+        for (param, term) in rc_func.parameters.iter().zip(params) {
+            scopes[0].insert(param.name.clone(), term);
+        }
+
+        if !self.infer_block(&rc_func.block_node, ret, &mut scopes, component) {
+            // falls off the end without ever returning a value
+            self.unify(ret, Term::Con(Type::Nil));
+        }
+    }
+
+    // returns whether the block contains a return statement
+    fn infer_block(&mut self, block: &BlockNode, ret: Term, scopes: &mut Vec<HashMap<String, Term>>, component: &[Rc<FuncNode>]) -> bool {
+        let mut has_return = false;
+        for rc_stmt in &block.statements {
+            match rc_stmt.deref() {
+                StmtNode::Let(letNode) => {
+                    let term = self.declare_let(letNode);
+                    if let Some(scope) = scopes.last_mut() {
+                        scope.insert(letNode.name.clone(), term);
+                    }
+                }
+                StmtNode::Assign(assignNode) => {
+                    let term = self.infer_expression(&assignNode.expr, scopes, component);
+                    if let Some(target) = self.lookup(&assignNode.name, scopes) {
+                        if !self.unify(target, term) {
+                            self.widen(target);
+                        }
+                    }
+                }
+                StmtNode::Return(returnNode) => {
+                    let term = self.infer_expression(&returnNode.expr, scopes, component);
+                    self.unify(ret, term);
+                    has_return = true;
+                }
+                StmtNode::Print(printNode) => {
+                    self.infer_expression(&printNode.expr, scopes, component);
+                }
+                StmtNode::While(whileNode) => {
+                    let term = self.infer_expression(&whileNode.condition, scopes, component);
+                    self.unify(term, Term::Con(Type::Bool));
+                    scopes.push(HashMap::new());
+                    has_return |= self.infer_block(&whileNode.body, ret, scopes, component);
+                    scopes.pop();
+                }
+                StmtNode::IfElse(ifNode) => {
+                    let term = self.infer_expression(&ifNode.condition, scopes, component);
+                    self.unify(term, Term::Con(Type::Bool));
+                    scopes.push(HashMap::new());
+                    has_return |= self.infer_block(&ifNode.ifBody, ret, scopes, component);
+                    scopes.pop();
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        scopes.push(HashMap::new());
+                        has_return |= self.infer_block(elseBody, ret, scopes, component);
+                        scopes.pop();
+                    }
+                }
+            }
+        }
+        has_return
+    }
+
+    fn infer_expression(&mut self, expr: &ExprNode, scopes: &[HashMap<String, Term>], component: &[Rc<FuncNode>]) -> Term {
+        match expr {
+            ExprNode::Var(name, ..) => {
+                match self.lookup(name, scopes) {
+                    Some(term) => term,
+                    None => self.fresh(),
+                }
+            }
+            ExprNode::Val(value) => self.annotated(Some(Type::of(value))),
+            ExprNode::String(_) => Term::Con(Type::Char),
+            ExprNode::Call(name, args, ..) => {
+                let arg_terms: Vec<Term> = args.iter()
+                    .map(|arg| self.infer_expression(arg, scopes, component))
+                    .collect();
+
+                match self.instantiate(name, component) {
+                    Some((params, ret)) => {
+                        for (param, arg) in params.into_iter().zip(arg_terms) {
+                            self.unify(param, arg);
+                        }
+                        ret
+                    }
+                    None => self.fresh(),
+                }
+            }
+            ExprNode::Add(a, b) => self.infer_binary("+", a, b, scopes, component),
+            ExprNode::Sub(a, b) => self.infer_binary("-", a, b, scopes, component),
+            ExprNode::Mul(a, b) => self.infer_binary("*", a, b, scopes, component),
+            ExprNode::Div(a, b) => self.infer_binary("/", a, b, scopes, component),
+            ExprNode::LessThan(a, b) => self.infer_binary("<", a, b, scopes, component),
+            ExprNode::GreaterThan(a, b) => self.infer_binary(">", a, b, scopes, component),
+            ExprNode::EqualTo(a, b) => self.infer_binary("==", a, b, scopes, component),
+            ExprNode::LessThanEq(a, b) => self.infer_binary("<=", a, b, scopes, component),
+            ExprNode::GreaterThanEq(a, b) => self.infer_binary(">=", a, b, scopes, component),
+            ExprNode::NotEqualTo(a, b) => self.infer_binary("!=", a, b, scopes, component),
+            ExprNode::And(a, b) | ExprNode::Or(a, b) => {
+                let left = self.infer_expression(a, scopes, component);
+                let right = self.infer_expression(b, scopes, component);
+                self.unify(left, Term::Con(Type::Bool));
+                self.unify(right, Term::Con(Type::Bool));
+                Term::Con(Type::Bool)
+            }
+            ExprNode::Not(a) => {
+                let operand = self.infer_expression(a, scopes, component);
+                self.unify(operand, Term::Con(Type::Bool));
+                Term::Con(Type::Bool)
+            }
+        }
+    }
+
+    fn infer_binary(&mut self, op: &str, a: &ExprNode, b: &ExprNode, scopes: &[HashMap<String, Term>], component: &[Rc<FuncNode>]) -> Term {
+        let left = self.infer_expression(a, scopes, component);
+        let right = self.infer_expression(b, scopes, component);
+
+        // any two values can be compared for equality, so it says nothing about the operands
+        if op == "==" || op == "!=" {
+            return Term::Con(Type::Bool);
+        }
+
+        match (self.resolve(left), self.resolve(right)) {
+            (Term::Con(t), _) | (_, Term::Con(t)) if t.is_dynamic() => {
+                match op {
+                    "+" | "-" | "*" | "/" => self.fresh(),
+                    _ => Term::Con(Type::Bool),
+                }
+            }
+            (Term::Con(l), Term::Con(r)) => {
+                match Type::binary(op, l, r) {
+                    Some(t) if !t.is_dynamic() => Term::Con(t),
+                    _ => self.fresh(),
+                }
+            }
+            (Term::Con(l), unknown @ Term::Var(_)) => self.infer_operand(op, l, unknown, true),
+            (unknown @ Term::Var(_), Term::Con(r)) => self.infer_operand(op, r, unknown, false),
+            (l @ Term::Var(_), r @ Term::Var(_)) => {
+                // the operands may legally differ, like an int32 and a flt32, so both stay open
+                match op {
+                    "+" | "-" | "*" | "/" if l == r => l,
+                    "+" | "-" | "*" | "/" => self.fresh(),
+                    _ => Term::Con(Type::Bool),
+                }
+            }
+        }
+    }
+
+    fn infer_operand(&mut self, op: &str, known: Type, unknown: Term, known_is_left: bool) -> Term {
+        let candidates = Type::operand_candidates(op, known, known_is_left);
+
+        // pin down the unknown operand only if there is a single choice, a numeric operand
+        // stays open since int32 and flt32 mix
+        if let [(other, result)] = candidates[..] {
+            self.unify(unknown, Term::Con(other));
+            return Term::Con(result);
+        }
+
+        match candidates.first() {
+            Some((_, result)) if candidates.iter().all(|(_, r)| r == result) => Term::Con(*result),
+            _ => self.fresh(),
+        }
+    }
+
+    fn declare_let(&mut self, let_node: &LetNode) -> Term {
+        let term = match let_node.let_type {
+            Some(t) => Term::Con(t),
+            None => {
+                let term = self.fresh();
+                if let Term::Var(v) = term {
+                    self.unannotated.insert(v);
+                }
+                let value = self.annotated(Some(Type::of(&let_node.value)));
+                self.unify(term, value);
+                term
+            }
+        };
+        self.lets.push((let_node as *const LetNode, term));
+        term
+    }
+
+    fn lookup(&self, name: &String, scopes: &[HashMap<String, Term>]) -> Option<Term> {
+        for scope in scopes.iter().rev() {
+            if let Some(term) = scope.get(name) {
+                return Some(*term);
+            }
+        }
+        self.globals.get(name).copied()
+    }
+
+    // parameter and return terms of a callee, fresh for every call of a generalized function
+    fn instantiate(&mut self, name: &String, component: &[Rc<FuncNode>]) -> Option<(Vec<Term>, Term)> {
+        let terms = self.funcs.get(name)?;
+        let (params, ret, generalized) = (terms.params.clone(), terms.ret, terms.generalized.clone());
+        if component.iter().any(|rc_func| &rc_func.name == name) {
+            return Some((params, ret));
+        }
+
+        let mut fresh = HashMap::new();
+        let mut copy = |inference: &mut Self, term: Term| {
+            match inference.resolve(term) {
+                Term::Var(v) if generalized.contains(&v) => {
+                    *fresh.entry(v).or_insert_with(|| inference.fresh())
+                }
+                resolved => resolved,
+            }
+        };
+        let params = params.into_iter().map(|param| copy(self, param)).collect();
+        let ret = copy(self, ret);
+        Some((params, ret))
+    }
+
+    fn generalize(&mut self, component: &[Rc<FuncNode>]) {
+        let global_terms: Vec<Term> = self.globals.values().copied().collect();
+        let bound: HashSet<usize> = global_terms.into_iter()
+            .filter_map(|term| self.free_var(term))
+            .collect();
+
+        for rc_func in component {
+            let terms = &self.funcs[&rc_func.name];
+            let all: Vec<Term> = terms.params.iter().copied().chain([terms.ret]).collect();
+            let generalized = all.into_iter()
+                .filter_map(|term| self.free_var(term))
+                .filter(|v| !bound.contains(v))
+                .collect();
+            if let Some(terms) = self.funcs.get_mut(&rc_func.name) {
+                terms.generalized = generalized;
+            }
+        }
+    }
+
+    // strongly connected components of the call graph (Tarjan), callees before callers
+    fn call_graph_components(&self) -> Vec<Vec<Rc<FuncNode>>> {
+        let funcs = &self.program.func_nodes;
+        let index_of: HashMap<&String, usize> = funcs.iter()
+            .enumerate()
+            .map(|(i, rc_func)| (&rc_func.name, i))
+            .collect();
+        let edges: Vec<Vec<usize>> = funcs.iter()
+            .map(|rc_func| {
+                let mut callees = vec![];
+                rc_func.block_node.collect_calls(&mut callees);
+                callees.iter().filter_map(|name| index_of.get(name).copied()).collect()
+            })
+            .collect();
+
+        let mut tarjan = Tarjan {
+            edges: &edges,
+            index: vec![None; funcs.len()],
+            low: vec![0; funcs.len()],
+            on_stack: vec![false; funcs.len()],
+            stack: vec![],
+            next: 0,
+            components: vec![],
+        };
+        for v in 0..funcs.len() {
+            if tarjan.index[v].is_none() {
+                tarjan.connect(v);
+            }
+        }
+
+        tarjan.components.into_iter()
+            .map(|component| component.into_iter().map(|i| funcs[i].clone()).collect())
+            .collect()
+    }
+
+    fn table(&self) -> TypeTable {
+        let mut table = TypeTable::default();
+
+        for rc_func in &self.program.func_nodes {
+            let terms = &self.funcs[&rc_func.name];
+            let mut generics = HashMap::new();
+            let mut export = |term: Term| {
+                match self.resolve(term) {
+                    Term::Con(t) => t,
+                    Term::Var(v) if terms.generalized.contains(&v) => {
+                        let next = generics.len();
+                        Type::Generic(*generics.entry(v).or_insert(next))
+                    }
+                    Term::Var(_) => Type::Dynamic,
+                }
+            };
+            let params = terms.params.iter().map(|term| export(*term)).collect();
+            let ret = export(terms.ret);
+            table.signatures.insert(rc_func.name.clone(), Signature { params, ret });
+        }
+
+        for (name, term) in &self.globals {
+            table.globals.insert(name.clone(), self.concrete(*term));
+        }
+        for (let_node, term) in &self.lets {
+            table.lets.insert(*let_node, self.concrete(*term));
+        }
+
+        table
+    }
+}
+
+// union-find over type variables
+impl<'a> Inference<'a> {
+    fn fresh(&mut self) -> Term {
+        self.bindings.push(None);
+        Term::Var(self.bindings.len() - 1)
+    }
+
+    // a fresh variable for missing or dynamic types
+    fn annotated(&mut self, t: Option<Type>) -> Term {
+        match t {
+            Some(t) if !t.is_dynamic() => Term::Con(t),
+            _ => self.fresh(),
+        }
+    }
+
+    fn resolve(&self, term: Term) -> Term {
+        let mut term = term;
+        while let Term::Var(v) = term {
+            match self.bindings[v] {
+                Some(bound) => term = bound,
+                None => break,
+            }
+        }
+        term
+    }
+
+    fn unify(&mut self, a: Term, b: Term) -> bool {
+        match (self.resolve(a), self.resolve(b)) {
+            (Term::Con(x), Term::Con(y)) => x.is_compatible(&y),
+            (Term::Var(x), Term::Var(y)) if x == y => true,
+            (Term::Var(x), other) | (other, Term::Var(x)) => {
+                self.bindings[x] = Some(other);
+                true
+            }
+        }
+    }
+
+    // makes an unannotated let dynamic, along with everything that shares its type
+    fn widen(&mut self, term: Term) {
+        let Term::Var(v) = term else { return };
+        if !self.unannotated.contains(&v) {
+            return;
+        }
+        let mut last = v;
+        while let Some(Term::Var(next)) = self.bindings[last] {
+            last = next;
+        }
+        self.bindings[last] = Some(Term::Con(Type::Dynamic));
+    }
+
+    fn free_var(&self, term: Term) -> Option<usize> {
+        match self.resolve(term) {
+            Term::Var(v) => Some(v),
+            Term::Con(_) => None,
+        }
+    }
+
+    fn concrete(&self, term: Term) -> Type {
+        match self.resolve(term) {
+            Term::Con(t) => t,
+            Term::Var(_) => Type::Dynamic,
+        }
+    }
+}
+
+struct Tarjan<'e> {
+    edges: &'e [Vec<usize>],
+    index: Vec<Option<usize>>,
+    low: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next: usize,
+    components: Vec<Vec<usize>>,
+}
+
+impl<'e> Tarjan<'e> {
+    fn connect(&mut self, v: usize) {
+        self.index[v] = Some(self.next);
+        self.low[v] = self.next;
+        self.next += 1;
+        self.stack.push(v);
+        self.on_stack[v] = true;
+
+        for &w in &self.edges[v] {
+            match self.index[w] {
+                None => {
+                    self.connect(w);
+                    self.low[v] = self.low[v].min(self.low[w]);
+                }
+                Some(index) if self.on_stack[w] => {
+                    self.low[v] = self.low[v].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+
+        if Some(self.low[v]) == self.index[v] {
+            let mut component = vec![];
+            while let Some(w) = self.stack.pop() {
+                self.on_stack[w] = false;
+                component.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::diagnostic::Diagnostics;
+    use crate::lexer::Lexer;
+    use crate::parser::DescentParser;
+    use crate::types::Type;
+
+    fn signatures(source: &str) -> super::TypeTable {
+        let diagnostics = Rc::new(Diagnostics::new("<test>", source));
+        let mut parser = DescentParser::new(Lexer::new(source.to_string()), diagnostics);
+        let program = parser.analyze();
+        super::Inference::infer(&program)
+    }
+
+    #[test]
+    fn it_keeps_numeric_operands_open() {
+        let table = signatures("func dec(n) [ return n - 1; ] func less(n) [ return n < 1; ]");
+
+        assert_eq!(table.signatures["dec"].params, vec![Type::Generic(0)]);
+        assert_eq!(table.signatures["less"].params, vec![Type::Generic(0)]);
+        assert_eq!(table.signatures["less"].ret, Type::Bool);
+    }
+
+    #[test]
+    fn it_infers_parameters_with_a_single_choice() {
+        let table = signatures("func flip(b) [ return not b; ] func quote(s) [ return true + s; ]");
+
+        assert_eq!(table.signatures["flip"].params, vec![Type::Bool]);
+        assert_eq!(table.signatures["quote"].params, vec![Type::Char]);
+        assert_eq!(table.signatures["quote"].ret, Type::Char);
+    }
+
+    #[test]
+    fn it_generalizes_unconstrained_functions() {
+        let table = signatures("func add(a, b) [ return a + b; ] func main() [ print add(1, 2); print add(1.5, 2.5); ]");
+        let signature = &table.signatures["add"];
+
+        assert_eq!(signature.params, vec![Type::Generic(0), Type::Generic(1)]);
+        assert_eq!(signature.ret, Type::Generic(2));
+    }
+
+    #[test]
+    fn it_does_not_constrain_the_operands_of_equality() {
+        let table = signatures("func same(a, b) [ return a == b; ] func zero(n) [ return n == 0; ]");
+
+        assert_eq!(table.signatures["same"].params, vec![Type::Generic(0), Type::Generic(1)]);
+        assert_eq!(table.signatures["zero"].params, vec![Type::Generic(0)]);
+    }
+
+    #[test]
+    fn it_widens_lets_assigned_different_types() {
+        let table = signatures("let g; func main() [ let x = 1; g = true; x = 2; g = \"a\"; ]");
+
+        assert_eq!(table.globals["g"], Type::Dynamic);
+        assert_eq!(table.signatures["main"].ret, Type::Nil);
+    }
+
+    #[test]
+    fn it_infers_nil_for_functions_without_return() {
+        let table = signatures("func main() [ print 1; ]");
+
+        assert_eq!(table.signatures["main"].ret, Type::Nil);
+    }
+
+    #[test]
+    fn it_infers_recursive_functions() {
+        let table = signatures("func fact(n) [ if n < 2 [ return 1; ] else [ return n * fact(n - 1); ] ]");
+        let signature = &table.signatures["fact"];
+
+        assert_eq!(signature.params, vec![Type::Generic(0)]);
+        assert_eq!(signature.ret, Type::Int32);
+    }
+}
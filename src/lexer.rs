@@ -2,6 +2,8 @@
 #![allow(non_snake_case)]
 #![allow(dead_code)]
 
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
 use crate::token::Token;
 
 #[derive(Debug)]
@@ -23,6 +25,12 @@ pub struct Lexer {
     pub current_token: Token,
     pub buffer_string: String,
     pub token_list: Vec<Token>,
+    pub token_start: usize,
+    line_starts: Vec<usize>,
+    // the input by char, positions and spans count chars rather than bytes
+    input_chars: Vec<char>,
+    // malformed input found since the last `take_errors`, the lexer skips over it
+    errors: Vec<Diagnostic>,
 }
 
 impl Lexer {
@@ -33,28 +41,67 @@ impl Lexer {
         self.current_token = Token::UNDEFINED;
         self.buffer_string.clear();
         self.token_list.clear();
+        self.token_start = 0;
+        self.line_starts = Self::find_line_starts(&self.input_string);
+        self.input_chars = self.input_string.chars().collect();
+        self.errors.clear();
     }
 
     pub fn new(input: String) -> Lexer {
         Lexer {
+            line_starts: Self::find_line_starts(&input),
+            input_chars: input.chars().collect(),
             input_string: input,
             input_position: 0,
             current_state: LexerState::Initial,
             current_token: Token::UNDEFINED,
             buffer_string: String::new(),
             token_list: Vec::new(),
+            token_start: 0,
+            errors: Vec::new(),
         }
     }
 
+    // char offsets at which each line of the input starts
+    fn find_line_starts(input: &str) -> Vec<usize> {
+        let mut line_starts = vec![0];
+        for (offset, c) in input.chars().enumerate() {
+            if c == '\n' {
+                line_starts.push(offset + 1);
+            }
+        }
+        line_starts
+    }
+
+    // location of the current token
+    pub fn span(&self) -> Span {
+        let line = match self.line_starts.binary_search(&self.token_start) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let col = self.token_start - self.line_starts[line];
+        Span::new(self.token_start, self.input_position.max(self.token_start), line + 1, col + 1)
+    }
+
+    pub fn take_errors(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn error(&mut self, code: &'static str, message: String, label: &str) {
+        let diagnostic = Diagnostic::error(code, message).with_primary(self.span(), label);
+        self.errors.push(diagnostic);
+    }
+
     pub fn advance(&mut self) {
         self.buffer_string.clear();
 
-        while self.input_string.len() > self.input_position {
-            let c: char = self.input_string.chars().nth(self.input_position).unwrap();
+        while self.input_chars.len() > self.input_position {
+            let c: char = self.input_chars[self.input_position];
             self.input_position += 1;
 
             match self.current_state {
                 LexerState::Initial => {
+                    self.token_start = self.input_position - 1;
                     match c {
                         '(' => {
                             self.current_token = Token::PARENS_L;
@@ -106,6 +153,11 @@ impl Lexer {
                             self.current_state = LexerState::Initial;
                             return;
                         }
+                        '#' => {
+                            self.current_token = Token::HASH_SIGN;
+                            self.current_state = LexerState::Initial;
+                            return;
+                        }
                         '+' => {
                             self.current_token = Token::OP_ADD;
                             self.current_state = LexerState::Initial;
@@ -166,8 +218,8 @@ impl Lexer {
                             self.current_state = LexerState::Number;
                         }
                         _ => {
-                            self.current_token = Token::UNDEFINED;
-                            self.input_position += 1;
+                            self.error("E0002", format!("unknown character '{c}'"), "not part of any token");
+                            self.current_state = LexerState::Initial;
                         }
                     }
                 }
@@ -183,10 +235,10 @@ impl Lexer {
                     return;
                 }
                 LexerState::Operator => {
-                    if c != ' ' && !(self.input_position == self.input_string.len()) {
+                    if c != ' ' && !(self.input_position == self.input_chars.len()) {
                         self.buffer_string.push(c);
                     } else {
-                        if self.input_position == self.input_string.len() && c != ' ' {
+                        if self.input_position == self.input_chars.len() && c != ' ' {
                             self.buffer_string.push(c);
                         }
                         let operator = self.buffer_string.clone();
@@ -219,9 +271,9 @@ impl Lexer {
                                 return;
                             }
                             _ => {
-                                self.current_token = Token::UNDEFINED;
+                                self.error("E0002", format!("unknown operator '{operator}'"), "not part of any token");
+                                self.buffer_string.clear();
                                 self.current_state = LexerState::Initial;
-                                return;
                             }
                         };
                     }
@@ -238,10 +290,10 @@ impl Lexer {
                     return;
                 }
                 LexerState::Identifier => {
-                    if (c.is_ascii_alphanumeric() || c == '_') && !(self.input_position == self.input_string.len()) {
+                    if (c.is_ascii_alphanumeric() || c == '_') && !(self.input_position == self.input_chars.len()) {
                         self.buffer_string.push(c);
                     } else {
-                        if self.input_position == self.input_string.len() && c.is_ascii_alphanumeric() {
+                        if self.input_position == self.input_chars.len() && c.is_ascii_alphanumeric() {
                             self.buffer_string.push(c);
                         }
                         let identifier = self.buffer_string.clone();
@@ -346,17 +398,22 @@ impl Lexer {
                         }
                         _ => {
                             let maybe_number = self.buffer_string.clone();
+                            self.current_state = LexerState::Initial;
+                            self.input_position -= 1;
 
                             if maybe_number.contains('.') {
-                                let float_val: f32 = maybe_number.parse().unwrap();
+                                let float_val: f32 = maybe_number.parse().unwrap_or_else(|_| {
+                                    self.error("E0004", format!("invalid float literal '{maybe_number}'"), "not a number");
+                                    0.0
+                                });
                                 self.current_token = Token::LIT_FLT32(float_val);
                             } else {
-                                let int_val: i32 = maybe_number.parse().unwrap();
+                                let int_val: i32 = maybe_number.parse().unwrap_or_else(|_| {
+                                    self.error("E0004", format!("integer literal '{maybe_number}' does not fit in int32"), "out of range");
+                                    0
+                                });
                                 self.current_token = Token::LIT_INT32(int_val);
                             }
-
-                            self.current_state = LexerState::Initial;
-                            self.input_position -= 1;
                             return;
                         }
                     }
@@ -374,7 +431,11 @@ impl Lexer {
                 LexerState::Char => {
                     if c == '\'' {
                         let literal = self.buffer_string.clone();
-                        self.current_token = Token::LIT_CHAR(literal.chars().nth(0).unwrap());
+                        let first = literal.chars().nth(0).unwrap_or_else(|| {
+                            self.error("E0003", "empty character literal".to_string(), "expected a character");
+                            ' '
+                        });
+                        self.current_token = Token::LIT_CHAR(first);
                         self.current_state = LexerState::Initial;
                         return;
                     } else {
@@ -384,8 +445,14 @@ impl Lexer {
             }
         }
 
-        if self.input_position >= self.input_string.len() {
+        if let LexerState::String | LexerState::Char = self.current_state {
+            self.error("E0003", "unterminated literal".to_string(), "missing closing quote");
+            self.current_state = LexerState::Initial;
+        }
+
+        if self.input_position >= self.input_chars.len() {
             self.current_token = Token::EOI;
+            self.token_start = self.input_position;
         }
     }
 
@@ -639,4 +706,59 @@ mod tests {
 
         assert_eq!(lexer.token_list, expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_tracks_token_positions() {
+        let mut lexer = super::Lexer::new("let x;\n  x = add(1, 2);".to_string());
+        let mut spans = vec![];
+        lexer.advance();
+        while lexer.current() != super::Token::EOI {
+            spans.push(lexer.span());
+            lexer.advance();
+        }
+
+        assert_eq!(spans[0], crate::span::Span::new(0, 3, 1, 1));
+        assert_eq!(spans[3], crate::span::Span::new(9, 10, 2, 3));
+        assert_eq!(spans[5], crate::span::Span::new(13, 16, 2, 7));
+    }
+
+    #[test]
+    fn it_reports_and_skips_malformed_input() {
+        let mut lexer = super::Lexer::new("x @ 99999999999 \"open".to_string());
+        let mut tokens = vec![];
+        lexer.advance();
+        while lexer.current() != super::Token::EOI {
+            tokens.push(lexer.current());
+            lexer.advance();
+        }
+
+        assert_eq!(tokens, vec![super::Token::ID("x".to_string()), super::Token::LIT_INT32(0)]);
+        let codes: Vec<&str> = lexer.take_errors().into_iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["E0002", "E0004", "E0003"]);
+        assert!(lexer.take_errors().is_empty());
+    }
+
+    #[test]
+    fn it_can_lex_non_ascii_input() {
+        let mut lexer = super::Lexer::new("print \"é\"; é x;".to_string());
+        let mut tokens = vec![];
+        let mut spans = vec![];
+        lexer.advance();
+        while lexer.current() != super::Token::EOI {
+            tokens.push(lexer.current());
+            spans.push(lexer.span());
+            lexer.advance();
+        }
+
+        assert_eq!(tokens, vec![
+            super::Token::KW_PRINT,
+            super::Token::LIT_STRING("é".to_string()),
+            super::Token::SEMICOLON,
+            super::Token::ID("x".to_string()),
+            super::Token::SEMICOLON,
+        ]);
+        assert_eq!(spans[3], crate::span::Span::new(13, 14, 1, 14));
+        let codes: Vec<&str> = lexer.take_errors().into_iter().map(|error| error.code).collect();
+        assert_eq!(codes, vec!["E0002"]);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+
+/// What happens when a lint finds something.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    /// Level named by a function attribute, like `allow` in `#[allow(unused_variable)]`.
+    pub fn from_attribute(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Allow => write!(f, "allow"),
+            Level::Warn => write!(f, "warn"),
+            Level::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+/// A check of the analyzer whose findings are warnings unless configured otherwise.
+#[derive(Debug)]
+pub struct Lint {
+    pub name: &'static str,
+    pub default: Level,
+    pub description: &'static str,
+}
+
+pub const UNUSED_VARIABLE: Lint = Lint {
+    name: "unused-variable",
+    default: Level::Warn,
+    description: "variables and parameters that are never read or assigned",
+};
+
+pub const UNUSED_FUNCTION: Lint = Lint {
+    name: "unused-function",
+    default: Level::Warn,
+    description: "functions that are never called from main",
+};
+
+pub const UNREACHABLE_CODE: Lint = Lint {
+    name: "unreachable-code",
+    default: Level::Warn,
+    description: "statements after return and branches behind constant conditions",
+};
+
+pub const MISSING_RETURN: Lint = Lint {
+    name: "missing-return",
+    default: Level::Warn,
+    description: "uncalled functions that return a value on some paths only",
+};
+
+pub const SHADOWING: Lint = Lint {
+    name: "shadowing",
+    default: Level::Allow,
+    description: "variables and parameters that hide an outer declaration of the same name",
+};
+
+pub const LINTS: [&Lint; 5] = [&UNUSED_VARIABLE, &UNUSED_FUNCTION, &UNREACHABLE_CODE, &MISSING_RETURN, &SHADOWING];
+
+/// Finds a lint by name, source attributes spell it with underscores like `unused_variable`.
+pub fn find(name: &str) -> Option<&'static Lint> {
+    let name = name.replace('_', "-");
+    LINTS.iter().copied().find(|lint| lint.name == name)
+}
+
+/// Lint levels set on the command line, the defaults for every function.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl LintLevels {
+    pub fn new() -> LintLevels {
+        LintLevels::default()
+    }
+
+    pub fn set(&mut self, lint: &Lint, level: Level) {
+        self.levels.insert(lint.name, level);
+    }
+
+    pub fn get(&self, lint: &Lint) -> Level {
+        self.levels.get(lint.name).copied().unwrap_or(lint.default)
+    }
+
+    /// True if the level was set on the command line instead of being the default.
+    pub fn is_set(&self, lint: &Lint) -> bool {
+        self.levels.contains_key(lint.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, Level, LintLevels, SHADOWING, UNUSED_VARIABLE};
+
+    #[test]
+    fn it_finds_lints_spelled_with_dashes_or_underscores() {
+        assert_eq!(find("unused-variable").map(|lint| lint.name), Some("unused-variable"));
+        assert_eq!(find("unused_variable").map(|lint| lint.name), Some("unused-variable"));
+        assert!(find("unused").is_none());
+    }
+
+    #[test]
+    fn it_falls_back_to_the_default_level() {
+        let mut levels = LintLevels::new();
+        assert_eq!(levels.get(&UNUSED_VARIABLE), Level::Warn);
+        assert_eq!(levels.get(&SHADOWING), Level::Allow);
+        assert!(!levels.is_set(&SHADOWING));
+
+        levels.set(&SHADOWING, Level::Deny);
+        assert_eq!(levels.get(&SHADOWING), Level::Deny);
+        assert!(levels.is_set(&SHADOWING));
+    }
+}
@@ -6,6 +6,7 @@ pub enum Level {
     Info,
     Debug,
     Warn,
+    Error,
     None,
 }
 
@@ -14,21 +15,28 @@ pub struct Logger {
 }
 
 impl Logger {
+    // everything goes to stderr, stdout only carries the output of the program
     fn log(level: Level, message: &str) {
         let logger = LOGGER.lock().unwrap();
+        if let Level::None = logger.level {
+            return;
+        }
         match level {
             Level::Info => {
                 if let Level::Info | Level::Debug | Level::Warn = logger.level {
-                    println!("[INFO] {}", message);
+                    eprintln!("[INFO] {}", message);
                 }
             }
             Level::Debug => {
                 if let Level::Debug | Level::Warn = logger.level {
-                    println!("[DEBUG] {}", message);
+                    eprintln!("[DEBUG] {}", message);
                 }
             }
             Level::Warn => {
-                println!("[WARN] {}", message);
+                eprintln!("[WARN] {}", message);
+            }
+            Level::Error => {
+                eprintln!("[ERROR] {}", message);
             }
             Level::None => {}
         }
@@ -45,6 +53,10 @@ impl Logger {
     pub fn warn(message: &str) {
         Self::log(Level::Warn, message);
     }
+
+    pub fn error(message: &str) {
+        Self::log(Level::Error, message);
+    }
 }
 
 lazy_static! {
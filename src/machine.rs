@@ -1,25 +1,272 @@
 use std::rc::Rc;
+use std::time::Duration;
 
 use crate::analyzer::Analyzer;
+use crate::bytecode::Compiler;
+use crate::context::{Context, DEFAULT_MAX_CALL_DEPTH};
+use crate::diagnostic::Diagnostics;
+use crate::evaluator::Overflow;
 use crate::executor::Executor;
+use crate::lint::LintLevels;
+use crate::optimizer::Optimizer;
 use crate::tree::ProgramNode;
+use crate::vm::Vm;
+
+/// What executes a compiled program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Walks the tree of the program, the reference for what a program does.
+    #[default]
+    Tree,
+    /// Compiles the program to bytecode and runs it on a stack based virtual machine.
+    Vm,
+}
+
+/// Why the machine stopped, each kind of failure ends the process with its own exit code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    Compile(String),
+    Runtime(String),
+    OutOfFuel(String),
+    Timeout(String),
+}
+
+impl Failure {
+    pub fn message(&self) -> &str {
+        match self {
+            Failure::Compile(message) | Failure::Runtime(message) => message,
+            Failure::OutOfFuel(message) | Failure::Timeout(message) => message,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Failure::Compile(_) => 1,
+            Failure::Runtime(_) => 2,
+            Failure::OutOfFuel(_) => 3,
+            Failure::Timeout(_) => 4,
+        }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure::Compile(message)
+    }
+}
 
 pub struct Machine {
     rc_program: Rc<ProgramNode>,
+    // 0 runs the analyzed program as is, 1 and above fold and simplify it first
+    opt_level: u8,
+    lints: LintLevels,
+    overflow: Overflow,
+    max_call_depth: usize,
+    stack_size: Option<usize>,
+    fuel: Option<u64>,
+    timeout: Option<Duration>,
+    backend: Backend,
+    diagnostics: Rc<Diagnostics>,
 }
 
 impl Machine {
-    pub fn new(rc_program: Rc<ProgramNode>) -> Machine {
+    pub fn new(rc_program: Rc<ProgramNode>, diagnostics: Rc<Diagnostics>) -> Machine {
         Machine {
-            rc_program
+            rc_program,
+            opt_level: 1,
+            lints: LintLevels::new(),
+            overflow: Overflow::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            stack_size: None,
+            fuel: None,
+            timeout: None,
+            backend: Backend::default(),
+            diagnostics,
         }
     }
 
-    pub fn run(&self) {
-        let analyzer = Analyzer::new(self.rc_program.clone());
-        analyzer.analyze();
+    pub fn set_opt_level(&mut self, opt_level: u8) {
+        self.opt_level = opt_level;
+    }
+
+    pub fn set_lint_levels(&mut self, lints: LintLevels) {
+        self.lints = lints;
+    }
 
-        let executor = Executor::new(self.rc_program.clone());
-        executor.execute();
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
     }
-}
\ No newline at end of file
+
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// Native stack of the thread the machine runs on, calls are refused before they exhaust it.
+    pub fn set_stack_size(&mut self, stack_size: Option<usize>) {
+        self.stack_size = stack_size;
+    }
+
+    /// Limits the statements and expressions the program may execute.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Limits the time the program may run, not counting compilation.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    pub fn run(&self) -> Result<(), Failure> {
+        let rc_program = self.compile()?;
+
+        let mut context = Context::new();
+        context.overflow = self.overflow;
+        context.max_call_depth = self.max_call_depth;
+        context.stack_size = self.stack_size;
+        context.fuel = self.fuel;
+        context.timeout = self.timeout;
+        let result = match self.backend {
+            Backend::Tree => Executor::new(rc_program, context).execute(),
+            Backend::Vm => Vm::new(Compiler::new(rc_program).compile(), context).execute(),
+        };
+        if let Err(error) = result {
+            let code = error.code();
+            self.diagnostics.report(error.into_diagnostic());
+            self.diagnostics.flush();
+            return Err(match code {
+                "E0509" => Failure::OutOfFuel("Execution ran out of fuel.".to_string()),
+                "E0510" => Failure::Timeout("Execution timed out.".to_string()),
+                _ => Failure::Runtime("Execution failed.".to_string()),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn dump_optimized(&self) -> Result<(), Failure> {
+        let rc_program = self.compile()?;
+        print!("{}", rc_program);
+
+        Ok(())
+    }
+
+    // analyzes the program and optimizes it according to the optimization level,
+    // the optimizer runs even after analysis errors so that its errors are reported too,
+    // denied lints are errors as well and stop the program before it is executed
+    fn compile(&self) -> Result<Rc<ProgramNode>, String> {
+        let mut analyzer = Analyzer::new(self.rc_program.clone(), self.diagnostics.clone());
+        analyzer.set_lint_levels(self.lints.clone());
+        let analyzed = analyzer.analyze();
+
+        let optimized = match self.opt_level {
+            0 => Ok(self.rc_program.clone()),
+            _ => {
+                let mut optimizer = Optimizer::new(self.rc_program.clone(), analyzer.into_types(), self.diagnostics.clone());
+                optimizer.set_overflow(self.overflow);
+                optimizer.optimize()
+            }
+        };
+
+        self.diagnostics.flush();
+        match self.diagnostics.error_count() {
+            0 => {
+                analyzed?;
+                optimized
+            }
+            1 => Err("Compilation failed due to 1 previous error.".to_string()),
+            n => Err(format!("Compilation failed due to {n} previous errors.")),
+        }
+    }
+
+    pub fn show_types(&self) -> Result<(), Failure> {
+        let mut analyzer = Analyzer::new(self.rc_program.clone(), self.diagnostics.clone());
+        analyzer.set_lint_levels(self.lints.clone());
+        let analyzed = analyzer.analyze();
+        self.diagnostics.flush();
+        analyzed?;
+
+        for signature in analyzer.signatures() {
+            println!("{}", signature);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use crate::analyzer::Analyzer;
+    use crate::bytecode::Compiler;
+    use crate::context::Context;
+    use crate::diagnostic::Diagnostics;
+    use crate::executor::Executor;
+    use crate::lexer::Lexer;
+    use crate::parser::DescentParser;
+    use crate::vm::Vm;
+
+    use super::{Backend, Failure, Machine};
+
+    // runs the source at the given optimization level
+    fn run(source: &str, opt_level: u8) -> Result<(), Failure> {
+        let diagnostics = Rc::new(Diagnostics::new("<test>", source));
+        let mut parser = DescentParser::new(Lexer::new(source.to_string()), diagnostics.clone());
+        let mut machine = Machine::new(Rc::new(parser.analyze()), diagnostics);
+        machine.set_opt_level(opt_level);
+        machine.run()
+    }
+
+    // time the backend takes to run main of the analyzed source
+    fn bench(source: &str, backend: Backend) -> Duration {
+        let diagnostics = Rc::new(Diagnostics::new("<test>", source));
+        let mut parser = DescentParser::new(Lexer::new(source.to_string()), diagnostics.clone());
+        let program = Rc::new(parser.analyze());
+        Analyzer::new(program.clone(), diagnostics).analyze().unwrap();
+
+        let start = Instant::now();
+        match backend {
+            Backend::Tree => Executor::new(program, Context::new()).execute().unwrap(),
+            Backend::Vm => Vm::new(Compiler::new(program).compile(), Context::new()).execute().unwrap(),
+        }
+        start.elapsed()
+    }
+
+    #[test]
+    fn it_checks_annotations_at_every_optimization_level() {
+        let source = include_str!("../test_files/annotated_calls.fe");
+        let expected = Err(Failure::Runtime("Execution failed.".to_string()));
+
+        assert_eq!(run(source, 0), expected);
+        assert_eq!(run(source, 1), expected);
+    }
+
+    #[test]
+    fn it_reports_globals_read_before_they_are_assigned() {
+        let source = include_str!("../test_files/unassigned_globals.fe");
+        assert!(matches!(run(source, 0), Err(Failure::Compile(_))));
+        // assigned in main and by a called function before they are read
+        let assigned = source.replace("    print total;\n", "").replace("return seen;", "seen = 1;\n    return seen;");
+        assert_eq!(run(&assigned, 0), Ok(()));
+    }
+
+    // the benchmarks print the times of both backends, run with `cargo test --release bench -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_factorial() {
+        let source = include_str!("../test_files/bench_factorial.fe");
+        println!("bench_factorial: tree {:?}, vm {:?}", bench(source, Backend::Tree), bench(source, Backend::Vm));
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_loop() {
+        let source = include_str!("../test_files/bench_loop.fe");
+        println!("bench_loop: tree {:?}, vm {:?}", bench(source, Backend::Tree), bench(source, Backend::Vm));
+    }
+}
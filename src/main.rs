@@ -2,15 +2,20 @@
 #![allow(unused_imports)]
 #![allow(dead_code)] // TODO: remove this
 
-use std::{error::Error, fs::read_to_string, path::PathBuf, rc::Rc};
+use std::{cell::Cell, error::Error, fs::read_to_string, path::PathBuf, rc::Rc, thread, time::Duration};
 
-use clap::{ArgGroup, Parser};
-use clap::builder::PossibleValue;
+use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use clap::builder::{PossibleValue, TypedValueParser};
 use tree::IfElseNode;
+use crate::context::DEFAULT_MAX_CALL_DEPTH;
+use crate::diagnostic::{Diagnostic, Diagnostics, MessageFormat};
+use crate::evaluator::Overflow;
+use crate::lint::{Lint, LintLevels};
 use crate::logger::{Logger, LOGGER};
 
-use crate::machine::Machine;
+use crate::machine::{Backend, Machine};
 use crate::parser::DescentParser;
+use crate::span::Span;
 use crate::tree::{AssignNode, BlockNode, ExprNode, FuncNode, LetNode, Parameter, PrintNode, ProgramNode, ReturnNode, StmtNode, WhileNode};
 use crate::value::Value;
 
@@ -30,6 +35,18 @@ mod parser;
 mod token;
 mod parser_pratt;
 mod logger;
+mod types;
+mod inference;
+mod span;
+mod optimizer;
+mod printer;
+mod diagnostic;
+mod lint;
+mod suggest;
+mod runtime_error;
+mod context;
+mod bytecode;
+mod vm;
 
 /*
 
@@ -70,38 +87,40 @@ fn grow_ast_program0() -> Rc<ProgramNode> {
     let mut program = ProgramNode::new();
 
     // global variables
-    let let_count = LetNode::new("count".to_string(), Value::Nil);
-    let let_help = LetNode::new("help".to_string(), Value::Nil);
+    let let_count = LetNode::new("count".to_string(), None, Value::Nil, Span::default());
+    let let_help = LetNode::new("help".to_string(), None, Value::Nil, Span::default());
     program.let_nodes.push(Rc::new(let_count));
     program.let_nodes.push(Rc::new(let_help));
 
     // add function
     let mut parameters_add = vec![];
-    parameters_add.push(Parameter::new("a".to_string()));
-    parameters_add.push(Parameter::new("b".to_string()));
+    parameters_add.push(Parameter::new("a".to_string(), None, Span::default()));
+    parameters_add.push(Parameter::new("b".to_string(), None, Span::default()));
 
     let mut block_add = BlockNode::new();
     let stmtAdd1 = StmtNode::Return(
         ReturnNode::new(ExprNode::Add(
-            Rc::new(ExprNode::Var("a".to_string())),
-            Rc::new(ExprNode::Var("b".to_string())),
-        ))
+            Rc::new(ExprNode::Var("a".to_string(), Span::default(), Cell::new(None))),
+            Rc::new(ExprNode::Var("b".to_string(), Span::default(), Cell::new(None))),
+        ), Span::default())
     );
     block_add.statements.push(Rc::new(stmtAdd1));
 
     let func_add = FuncNode::new(
         "add".to_string(),
         parameters_add,
-        block_add);
+        None,
+        block_add,
+        Span::default());
 
     program.func_nodes.push(Rc::new(func_add));
 
     // main function
     let mut parameters_main = vec![];
-    parameters_main.push(Parameter::new("argc".to_string()));
+    parameters_main.push(Parameter::new("argc".to_string(), None, Span::default()));
 
     let mut block_main = BlockNode::new();
-    let stmtMain1 = StmtNode::Let(LetNode::new("sum".to_string(), Value::Nil));
+    let stmtMain1 = StmtNode::Let(LetNode::new("sum".to_string(), None, Value::Nil, Span::default()));
     let stmtMain2 = StmtNode::Assign(
         AssignNode::new("sum".to_string(), ExprNode::Add(
             Rc::new(ExprNode::Val(Value::I32(3))),
@@ -109,21 +128,22 @@ fn grow_ast_program0() -> Rc<ProgramNode> {
                 Rc::new(ExprNode::Val(Value::I32(5))),
                 Rc::new(ExprNode::Val(Value::I32(7))),
             )),
-        ))
+        ), Span::default())
     );
     let stmtMain3 = StmtNode::Print(
-        PrintNode::new(ExprNode::Var("sum".to_string())));
+        PrintNode::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None)), Span::default()));
     let stmtMain4 = StmtNode::Assign(
         AssignNode::new(
             "sum".to_string(),
             ExprNode::Call(
                 "add".to_string(), vec![
-                    Rc::new(ExprNode::Var("sum".to_string())),
+                    Rc::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None))),
                     Rc::new(ExprNode::Val(Value::I32(1))),
-                ]),
+                ], Span::default(), Cell::new(None)),
+            Span::default(),
         ));
     let stmtMain5 = StmtNode::Print(
-        PrintNode::new(ExprNode::Var("sum".to_string())));
+        PrintNode::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None)), Span::default()));
 
     // block for while loop
     let mut whileBlock = BlockNode::new();
@@ -132,22 +152,25 @@ fn grow_ast_program0() -> Rc<ProgramNode> {
             "sum".to_string(),
             ExprNode::Call(
                 "add".to_string(), vec![
-                    Rc::new(ExprNode::Var("sum".to_string())),
+                    Rc::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None))),
                     Rc::new(ExprNode::Val(Value::I32(1))),
-                ]),
+                ], Span::default(), Cell::new(None)),
+            Span::default(),
         ));
     let stmtWhile2 = StmtNode::Print(
-        PrintNode::new(ExprNode::Var("sum".to_string())));
+        PrintNode::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None)), Span::default()));
     whileBlock.statements.push(Rc::new(stmtWhile1));
     whileBlock.statements.push(Rc::new(stmtWhile2));
 
     // while loop statement
     let stmtMain6 = StmtNode::While(WhileNode::new(
         ExprNode::LessThan(
-            Rc::new(ExprNode::Var("sum".to_string())),
+            Rc::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None))),
             Rc::new(ExprNode::Val(Value::I32(20))),
         ),
+        Span::default(),
         whileBlock,
+        Span::default(),
     ));
 
     // block for if
@@ -157,13 +180,14 @@ fn grow_ast_program0() -> Rc<ProgramNode> {
             "sum".to_string(),
             ExprNode::Call(
                 "add".to_string(), vec![
-                    Rc::new(ExprNode::Var("sum".to_string())),
+                    Rc::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None))),
                     Rc::new(ExprNode::Val(Value::I32(1))),
-                ]),
+                ], Span::default(), Cell::new(None)),
+            Span::default(),
         )
     );
     let stmtIf2 = StmtNode::Print(
-        PrintNode::new(ExprNode::Var("sum".to_string())));
+        PrintNode::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None)), Span::default()));
     ifBlock.statements.push(Rc::new(stmtIf1));
     ifBlock.statements.push(Rc::new(stmtIf2));
 
@@ -174,25 +198,28 @@ fn grow_ast_program0() -> Rc<ProgramNode> {
             "sum".to_string(),
             ExprNode::Call(
                 "add".to_string(), vec![
-                    Rc::new(ExprNode::Var("sum".to_string())),
+                    Rc::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None))),
                     Rc::new(ExprNode::Val(Value::I32(2))),
-                ]),
+                ], Span::default(), Cell::new(None)),
+            Span::default(),
         )
     );
     let stmtElse2 = StmtNode::Print(
-        PrintNode::new(ExprNode::Var("sum".to_string())));
+        PrintNode::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None)), Span::default()));
     elseBlock.statements.push(Rc::new(stmtElse1));
     elseBlock.statements.push(Rc::new(stmtElse2));
 
     // if else statement
     let stmtMain7 = StmtNode::IfElse(IfElseNode::new(
         ExprNode::EqualTo(
-            Rc::new(ExprNode::Var("sum".to_string())),
+            Rc::new(ExprNode::Var("sum".to_string(), Span::default(), Cell::new(None))),
             Rc::new(ExprNode::Val(Value::I32(21))),
         ),
+        Span::default(),
         ifBlock,
         None,
         //elseBlock.into()
+        Span::default(),
     ));
 
     // add statements to main block
@@ -202,14 +229,16 @@ fn grow_ast_program0() -> Rc<ProgramNode> {
     block_main.statements.push(Rc::new(stmtMain4));
     block_main.statements.push(Rc::new(stmtMain5));
     // debug print statement
-    block_main.statements.push(Rc::new(StmtNode::Print(PrintNode::new(ExprNode::Add(Rc::new(ExprNode::String("While loop".to_string())), Rc::new(ExprNode::Val(Value::I32(50))))))));
+    block_main.statements.push(Rc::new(StmtNode::Print(PrintNode::new(ExprNode::Add(Rc::new(ExprNode::String("While loop".to_string())), Rc::new(ExprNode::Val(Value::I32(50)))), Span::default()))));
     block_main.statements.push(Rc::new(stmtMain6));
     block_main.statements.push(Rc::new(stmtMain7));
 
     let func_main = FuncNode::new(
         "main".to_string(),
         parameters_main,
-        block_main);
+        None,
+        block_main,
+        Span::default());
 
     program.func_nodes.push(Rc::new(func_main));
 
@@ -226,27 +255,98 @@ fn run0() {
     // this is the machine that executes the ast
     // it has the analyzer and the program executor
     // TODO: should be pretty set, but will need to add some further logic later
-    let runtime = Machine::new(rc_program);
-    runtime.run();
+    let runtime = Machine::new(rc_program, Rc::new(Diagnostics::new("<test ast>", "")));
+    runtime.run().unwrap();
 }
 
-fn run_main(input: String) {
+fn run_main(file: &str, input: String, message_format: MessageFormat, lints: LintLevels, call_stack: usize, args: &Cli) {
+    // collects the errors and warnings of every phase, printed with the source lines they point at
+    let mut diagnostics = Diagnostics::new(file, &input);
+    diagnostics.set_format(message_format);
+    let diagnostics = Rc::new(diagnostics);
+
     let mut lexer = lexer::Lexer::new("".to_string());
     lexer.set_input(input);
 
-    let mut parser = DescentParser::new(lexer);
+    let mut parser = DescentParser::new(lexer, diagnostics.clone());
     let ast = parser.analyze();
 
+    // a broken program would only produce follow up errors in the analyzer
+    diagnostics.flush();
+    let parsed = match diagnostics.error_count() {
+        0 => Ok(()),
+        1 => Err("Parsing failed due to 1 previous error.".to_string()),
+        n => Err(format!("Parsing failed due to {n} previous errors.")),
+    };
+    if let Err(message) = parsed {
+        Logger::error(&message);
+        std::process::exit(1);
+    }
+
     // print ast
     Logger::debug(&format!("\n---------------------\nProgram AST:\n {ast:#?}\n---------------------", ast=ast));
 
-    let runtime = Machine::new(Rc::new(ast));
-    runtime.run();
+    let mut runtime = Machine::new(Rc::new(ast), diagnostics);
+    runtime.set_opt_level(args.opt_level);
+    runtime.set_lint_levels(lints);
+    runtime.set_max_call_depth(args.max_call_depth);
+    runtime.set_stack_size(Some(call_stack));
+    runtime.set_fuel(args.fuel);
+    runtime.set_timeout(args.timeout.map(Duration::from_millis));
+    runtime.set_backend(match args.backend.as_str() {
+        "tree" => Backend::Tree,
+        "vm" => Backend::Vm,
+        _ => panic!("Invalid backend: {}", args.backend)
+    });
+    runtime.set_overflow(match args.overflow.as_str() {
+        "wrap" => Overflow::Wrap,
+        "saturate" => Overflow::Saturate,
+        "error" => Overflow::Error,
+        _ => panic!("Invalid overflow behavior: {}", args.overflow)
+    });
+    let result = if args.show_types {
+        runtime.show_types()
+    } else if args.dump_optimized {
+        runtime.dump_optimized()
+    } else {
+        runtime.run()
+    };
+    // compile errors exit with 1 like parse errors, runtime errors with 2,
+    // running out of fuel with 3 and out of time with 4
+    if let Err(failure) = result {
+        Logger::error(failure.message());
+        std::process::exit(failure.exit_code());
+    }
 }
 
 
+// lint flags apply in the order they are given, so `-A unused-variable -W unused-variable` warns
+fn lint_levels(matches: &ArgMatches) -> LintLevels {
+    let mut flags = vec![];
+    for (id, level) in [("allow", lint::Level::Allow), ("warn", lint::Level::Warn), ("deny", lint::Level::Deny)] {
+        if let (Some(lints), Some(indices)) = (matches.get_many::<&'static Lint>(id), matches.indices_of(id)) {
+            flags.extend(indices.zip(lints).map(|(index, lint)| (index, *lint, level)));
+        }
+    }
+    flags.sort_by_key(|(index, _, _)| *index);
+
+    let mut lints = LintLevels::new();
+    for (_, lint, level) in flags {
+        lints.set(lint, level);
+    }
+    lints
+}
+
+fn parse_lint(name: &str) -> Result<&'static Lint, String> {
+    lint::find(name).ok_or_else(|| {
+        let known: Vec<&str> = lint::LINTS.iter().map(|lint| lint.name).collect();
+        format!("unknown lint, expected one of {}", known.join(", "))
+    })
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let args = Cli::from_arg_matches(&matches)?;
     // TODO: uncomment when ready to add cli support
     //
     // if args.tokenize {
@@ -259,7 +359,15 @@ fn main() -> Result<(), Box<dyn Error>> {
     //
     // println!("File contents:\n{}", read_to_string(args.file)?);
 
+    let message_format = match args.message_format.as_str() {
+        "human" => MessageFormat::Human,
+        "json" => MessageFormat::Json,
+        _ => panic!("Invalid message format: {}", args.message_format)
+    };
+
+    // json output is read by tools, which expect nothing but diagnostics on stderr
     let log_level = match args.loglevel.as_str() {
+        _ if message_format == MessageFormat::Json => logger::Level::None,
         "info" => logger::Level::Info,
         "debug" => logger::Level::Debug,
         "warn" => logger::Level::Warn,
@@ -275,12 +383,65 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // run0();
 
-    let input = read_to_string(args.file).expect("Failed to read input file.");
-    run_main(input);
+    let file = args.file.display().to_string();
+    let input = read_to_string(&args.file).expect("Failed to read input file.");
+    let lints = lint_levels(&matches);
+
+    // calls of script functions recurse on the native stack, so the interpreter runs on
+    // a thread with enough stack for the deepest recursion it allows, every call takes
+    // more of it the deeper blocks and expressions nest in the program
+    let stack_per_call = STACK_PER_CALL + nesting(&input) * STACK_PER_NESTING;
+    let stack_size = args.max_call_depth.saturating_add(1).saturating_mul(stack_per_call)
+        .saturating_add(INTERPRETER_STACK)
+        .min(MAX_INTERPRETER_STACK);
+    // calls are refused before they take the stack the interpreter needs besides them
+    let call_stack = stack_size.saturating_sub(INTERPRETER_STACK + stack_per_call);
+    let mut diagnostics = Diagnostics::new(&file, "");
+    diagnostics.set_format(message_format);
+    let interpreter = thread::Builder::new()
+        .name("interpreter".to_string())
+        .stack_size(stack_size)
+        .spawn(move || run_main(&file, input, message_format, lints, call_stack, &args));
+    // the system may refuse that much stack, which is no fault of the program
+    let interpreter = match interpreter {
+        Ok(interpreter) => interpreter,
+        Err(error) => {
+            diagnostics.report(
+                Diagnostic::error("E0511", format!("cannot start the interpreter with {} MiB of stack: {error}", stack_size / (1024 * 1024)))
+                    .with_help("use a lower '--max-call-depth', which needs less stack".to_string()));
+            diagnostics.flush();
+            Logger::error("Execution failed.");
+            std::process::exit(5);
+        }
+    };
+    if interpreter.join().is_err() {
+        std::process::exit(101);
+    }
 
     Ok(())
 }
 
+// deepest nesting of blocks and expressions in the program, parsed once more on the interpreter
+// thread, errors are reported there
+fn nesting(input: &str) -> usize {
+    let mut lexer = lexer::Lexer::new("".to_string());
+    lexer.set_input(input.to_string());
+    let mut parser = DescentParser::new(lexer, Rc::new(Diagnostics::new("", input)));
+    parser.analyze().nesting()
+}
+
+// native stack of the interpreter besides the calls of script functions
+const INTERPRETER_STACK: usize = 16 * 1024 * 1024;
+// native stack used by one call of a script function, unoptimized builds need several
+// times more than optimized ones
+const STACK_PER_CALL: usize = if cfg!(debug_assertions) { 64 * 1024 } else { 8 * 1024 };
+// native stack used by each block or operation nested in the body of a function
+const STACK_PER_NESTING: usize = if cfg!(debug_assertions) { 16 * 1024 } else { 1024 };
+// most stack the interpreter asks for, systems refuse a thread with several GiB of it
+const MAX_INTERPRETER_STACK: usize = 1024 * 1024 * 1024;
+// deepest recursion of functions without nesting that fits into the most stack the interpreter asks for
+const MAX_CALL_DEPTH: usize = (MAX_INTERPRETER_STACK - INTERPRETER_STACK) / STACK_PER_CALL;
+
 /// Iron Oxide Cli
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None, group = ArgGroup::new("action").required(false))]
@@ -292,6 +453,55 @@ struct Cli {
     #[arg(short, long, default_value = "info", value_parser = vec![PossibleValue::new("info"), PossibleValue::new("debug"), PossibleValue::new("warn"), PossibleValue::new("none")], group = "action")]
     loglevel: String,
 
+    /// Format of errors and warnings, json prints one object per line on stderr
+    #[arg(long = "message-format", default_value = "human", value_parser = vec![PossibleValue::new("human"), PossibleValue::new("json")])]
+    message_format: String,
+
+    /// Silence a lint, can be given several times
+    #[arg(short = 'A', long = "allow", value_name = "LINT", value_parser = parse_lint)]
+    allow: Vec<&'static Lint>,
+
+    /// Report a lint as warning, can be given several times
+    #[arg(short = 'W', long = "warn", value_name = "LINT", value_parser = parse_lint)]
+    warn: Vec<&'static Lint>,
+
+    /// Report a lint as error and do not run the program, can be given several times
+    #[arg(short = 'D', long = "deny", value_name = "LINT", value_parser = parse_lint)]
+    deny: Vec<&'static Lint>,
+
+    /// Print the inferred signature of every function instead of executing
+    #[arg(long = "show-types")]
+    show_types: bool,
+
+    /// Print the program after optimization instead of executing
+    #[arg(long = "dump-optimized")]
+    dump_optimized: bool,
+
+    /// Optimization level, 0 disables constant folding and algebraic simplification
+    #[arg(short = 'O', long = "opt-level", default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+    opt_level: u8,
+
+    /// What int32 arithmetic does when the result does not fit, also when folding constants
+    #[arg(long = "overflow", default_value = "error", value_parser = vec![PossibleValue::new("wrap"), PossibleValue::new("saturate"), PossibleValue::new("error")])]
+    overflow: String,
+
+    /// Calls of script functions that may be active at the same time, deeper recursion is a runtime error,
+    /// exit code 5 if the system refuses the stack they need
+    #[arg(long = "max-call-depth", default_value_t = DEFAULT_MAX_CALL_DEPTH, value_parser = clap::value_parser!(u32).range(1..=MAX_CALL_DEPTH as i64).map(|depth| depth as usize))]
+    max_call_depth: usize,
+
+    /// Statements and expressions the program may execute, running out stops it with exit code 3
+    #[arg(long = "fuel", value_name = "STEPS")]
+    fuel: Option<u64>,
+
+    /// Milliseconds the program may run, running over stops it with exit code 4
+    #[arg(long = "timeout", value_name = "MS")]
+    timeout: Option<u64>,
+
+    /// What executes the program, the tree walking interpreter or the bytecode virtual machine
+    #[arg(long = "backend", default_value = "tree", value_parser = vec![PossibleValue::new("tree"), PossibleValue::new("vm")])]
+    backend: String,
+
     // /// Tokenize the file
     // #[clap(short = 't', long = "tokenize", group = "action")]
     // tokenize: bool,
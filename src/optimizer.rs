@@ -0,0 +1,473 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::evaluator::{Evaluator, Overflow};
+use crate::inference::TypeTable;
+use crate::logger::Logger;
+use crate::span::Span;
+use crate::tree::{AssignNode, BlockNode, ExprNode, FuncNode, IfElseNode, PrintNode, ProgramNode, ReturnNode, StmtNode, WhileNode};
+use crate::types::Type;
+use crate::value::Value;
+
+pub struct Optimizer {
+    program: Rc<ProgramNode>,
+    // expression types found by the analyzer, keyed by the nodes of the unoptimized program
+    types: TypeTable,
+    diagnostics: Rc<Diagnostics>,
+    errors: Cell<usize>,
+    // statement being folded, constant expressions have no span of their own
+    statement: Cell<Span>,
+    // folded int32 arithmetic overflows like it would at runtime
+    overflow: Overflow,
+    // variables in reach, innermost scope last, true for those whose value is known to have its
+    // annotated type: parameters, which are checked at runtime, that the function never assigns
+    annotated: RefCell<Vec<HashMap<String, bool>>>,
+}
+
+impl Optimizer {
+    pub fn new(program: Rc<ProgramNode>, types: TypeTable, diagnostics: Rc<Diagnostics>) -> Optimizer {
+        Optimizer {
+            program,
+            types,
+            diagnostics,
+            errors: Cell::new(0),
+            statement: Cell::new(Span::default()),
+            overflow: Overflow::default(),
+            annotated: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    /// Rebuilds the analyzed program with every constant subexpression folded into a value
+    /// and algebraic identities like `x * 1` simplified.
+    pub fn optimize(&self) -> Result<Rc<ProgramNode>, String> {
+        Logger::info("Optimize.");
+        let func_nodes = self.program.func_nodes.iter()
+            .map(|rc_func| Rc::new(self.fold_function(rc_func)))
+            .collect();
+
+        match self.errors.get() {
+            0 => Ok(Rc::new(ProgramNode {
+                symbols: self.program.symbols.clone(),
+                let_nodes: self.program.let_nodes.clone(),
+                func_nodes,
+            })),
+            1 => Err("Optimization failed due to 1 previous error.".to_string()),
+            n => Err(format!("Optimization failed due to {n} previous errors.")),
+        }
+    }
+
+    fn error(&self, diagnostic: Diagnostic) {
+        self.diagnostics.report(diagnostic);
+        self.errors.set(self.errors.get() + 1);
+    }
+
+    fn fold_function(&self, rc_func: &FuncNode) -> FuncNode {
+        let params = rc_func.parameters.iter()
+            .map(|param| (param.name.clone(), param.param_type.is_some() && !rc_func.block_node.assigns(&param.name)))
+            .collect();
+        self.annotated.borrow_mut().push(params);
+        let mut func_node = FuncNode::new(
+            rc_func.name.clone(),
+            rc_func.parameters.clone(),
+            rc_func.return_type,
+            self.fold_block(&rc_func.block_node, &rc_func.name),
+            rc_func.span,
+        );
+        self.annotated.borrow_mut().pop();
+        func_node.attributes = rc_func.attributes.clone();
+        func_node
+    }
+
+    fn fold_block(&self, block: &BlockNode, func: &String) -> BlockNode {
+        self.annotated.borrow_mut().push(HashMap::new());
+        let block_node = BlockNode {
+            symbols: block.symbols.clone(),
+            statements: block.statements.iter()
+                .map(|rc_stmt| Rc::new(self.fold_statement(rc_stmt, func)))
+                .collect(),
+            slots: block.slots.clone(),
+        };
+        self.annotated.borrow_mut().pop();
+        block_node
+    }
+
+    fn fold_statement(&self, stmt: &StmtNode, func: &String) -> StmtNode {
+        self.statement.set(stmt.span());
+        match stmt {
+            StmtNode::Let(letNode) => {
+                // nothing checks the values assigned to a variable at runtime
+                if let Some(scope) = self.annotated.borrow_mut().last_mut() {
+                    scope.insert(letNode.name.clone(), false);
+                }
+                StmtNode::Let(letNode.clone())
+            }
+            StmtNode::Assign(assignNode) => {
+                let folded = AssignNode::new(assignNode.name.clone(), self.fold_expression(&assignNode.expr, func), assignNode.span);
+                folded.slot.set(assignNode.slot.get());
+                StmtNode::Assign(folded)
+            }
+            StmtNode::Return(returnNode) => {
+                let folded = ReturnNode::new(self.fold_expression(&returnNode.expr, func), returnNode.span);
+                folded.tail_call.set(returnNode.tail_call.get());
+                StmtNode::Return(folded)
+            }
+            StmtNode::Print(printNode) => StmtNode::Print(PrintNode::new(
+                self.fold_expression(&printNode.expr, func),
+                printNode.span,
+            )),
+            StmtNode::While(whileNode) => StmtNode::While(WhileNode::new(
+                self.fold_expression(&whileNode.condition, func),
+                whileNode.condition_span,
+                self.fold_block(&whileNode.body, func),
+                whileNode.span,
+            )),
+            StmtNode::IfElse(ifNode) => StmtNode::IfElse(IfElseNode::new(
+                self.fold_expression(&ifNode.condition, func),
+                ifNode.condition_span,
+                self.fold_block(&ifNode.ifBody, func),
+                ifNode.elseBody.as_ref().map(|elseBody| self.fold_block(elseBody, func)),
+                ifNode.span,
+            )),
+        }
+    }
+
+    fn fold_expression(&self, expr: &ExprNode, func: &String) -> ExprNode {
+        match expr {
+            ExprNode::Var(..) | ExprNode::Val(_) | ExprNode::String(_) => expr.clone(),
+            ExprNode::Call(name, args, span, slot) => ExprNode::Call(
+                name.clone(),
+                args.iter().map(|arg| Rc::new(self.fold_expression(arg, func))).collect(),
+                *span,
+                slot.clone(),
+            ),
+            ExprNode::Add(a, b) => self.fold_binary("+", a, b, ExprNode::Add, func),
+            ExprNode::Sub(a, b) => self.fold_binary("-", a, b, ExprNode::Sub, func),
+            ExprNode::Mul(a, b) => self.fold_binary("*", a, b, ExprNode::Mul, func),
+            ExprNode::Div(a, b) => self.fold_binary("/", a, b, ExprNode::Div, func),
+            ExprNode::LessThan(a, b) => self.fold_binary("<", a, b, ExprNode::LessThan, func),
+            ExprNode::GreaterThan(a, b) => self.fold_binary(">", a, b, ExprNode::GreaterThan, func),
+            ExprNode::EqualTo(a, b) => self.fold_binary("==", a, b, ExprNode::EqualTo, func),
+            ExprNode::LessThanEq(a, b) => self.fold_binary("<=", a, b, ExprNode::LessThanEq, func),
+            ExprNode::GreaterThanEq(a, b) => self.fold_binary(">=", a, b, ExprNode::GreaterThanEq, func),
+            ExprNode::NotEqualTo(a, b) => self.fold_binary("!=", a, b, ExprNode::NotEqualTo, func),
+            ExprNode::And(a, b) => self.fold_logical(false, a, b, ExprNode::And, func),
+            ExprNode::Or(a, b) => self.fold_logical(true, a, b, ExprNode::Or, func),
+            ExprNode::Not(a) => self.fold_not(a, func),
+        }
+    }
+
+    fn fold_binary(
+        &self,
+        op: &str,
+        a: &ExprNode,
+        b: &ExprNode,
+        rebuild: fn(Rc<ExprNode>, Rc<ExprNode>) -> ExprNode,
+        func: &String,
+    ) -> ExprNode {
+        let ints = self.type_of(a) == Type::Int32 && self.type_of(b) == Type::Int32;
+        let a = self.fold_expression(a, func);
+        let b = self.fold_expression(b, func);
+
+        if let (Some(value_a), Some(value_b)) = (Self::constant(&a), Self::constant(&b)) {
+            if let Some(value) = self.fold_values(op, value_a, value_b, func) {
+                return ExprNode::Val(value);
+            }
+        }
+        if ints && self.is_trusted(&a) && self.is_trusted(&b) {
+            if let Some(simplified) = Self::simplify_ints(op, &a, &b) {
+                return simplified;
+            }
+        }
+        rebuild(Rc::new(a), Rc::new(b))
+    }
+
+    // identities only hold for int32, other operands are rounded or rejected by the evaluator
+    fn simplify_ints(op: &str, a: &ExprNode, b: &ExprNode) -> Option<ExprNode> {
+        let int = |expr: &ExprNode| match expr {
+            ExprNode::Val(Value::I32(i)) => Some(*i),
+            _ => None,
+        };
+        match (op, int(a), int(b)) {
+            // x * 0 and 0 * x, unless that drops a call
+            ("*", _, Some(0)) if !Self::has_call(a) => Some(ExprNode::Val(Value::I32(0))),
+            ("*", Some(0), _) if !Self::has_call(b) => Some(ExprNode::Val(Value::I32(0))),
+            // x * 1, x + 0, x - 0 and x / 1
+            ("*", _, Some(1)) | ("+", _, Some(0)) | ("-", _, Some(0)) | ("/", _, Some(1)) => Some(a.clone()),
+            // 1 * x and 0 + x
+            ("*", Some(1), _) | ("+", Some(0), _) => Some(b.clone()),
+            _ => None,
+        }
+    }
+
+    // `decisive` is the left operand that short circuits, true for 'or' and false for 'and'
+    fn fold_logical(
+        &self,
+        decisive: bool,
+        a: &ExprNode,
+        b: &ExprNode,
+        rebuild: fn(Rc<ExprNode>, Rc<ExprNode>) -> ExprNode,
+        func: &String,
+    ) -> ExprNode {
+        let bool_a = self.type_of(a) == Type::Bool;
+        let bool_b = self.type_of(b) == Type::Bool;
+        let a = self.fold_expression(a, func);
+        // true or b, false and b: b is never evaluated, so neither is it folded nor are its errors reported
+        if matches!(a, ExprNode::Val(Value::Bool(v)) if v == decisive) {
+            return a;
+        }
+        let b = self.fold_expression(b, func);
+        let bool_a = bool_a && self.is_trusted(&a);
+        let bool_b = bool_b && self.is_trusted(&b);
+
+        match (&a, &b) {
+            // false or b, true and b
+            (ExprNode::Val(Value::Bool(v)), _) if *v != decisive && bool_b => b,
+            // b or true, b and false: b is still evaluated, so it must not call anything
+            (_, ExprNode::Val(Value::Bool(v))) if *v == decisive && bool_a && !Self::has_call(&a) => b,
+            // b or false, b and true
+            (_, ExprNode::Val(Value::Bool(v))) if *v != decisive && bool_a => a,
+            _ => rebuild(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    fn fold_not(&self, operand: &ExprNode, func: &String) -> ExprNode {
+        // not not b, where the inner operand is known to be a bool
+        let double = match operand {
+            ExprNode::Not(inner) => self.type_of(inner) == Type::Bool,
+            _ => false,
+        };
+        let folded = self.fold_expression(operand, func);
+
+        match folded {
+            ExprNode::Val(Value::Bool(v)) => ExprNode::Val(Value::Bool(!v)),
+            ExprNode::Not(inner) if double && self.is_trusted(&inner) => inner.deref().clone(),
+            _ => ExprNode::Not(Rc::new(folded)),
+        }
+    }
+
+    fn type_of(&self, expr: &ExprNode) -> Type {
+        match expr {
+            ExprNode::Val(value) => Type::of(value),
+            ExprNode::String(_) => Type::Char,
+            _ => self.types.expr_type(expr),
+        }
+    }
+
+    // whether the inferred type of a folded operand also holds at runtime: inference guesses
+    // the types of unannotated code, and such a variable may even still be nil. Annotated
+    // parameters and results are checked when a function is called and when it returns
+    fn is_trusted(&self, expr: &ExprNode) -> bool {
+        let annotated = self.annotated.borrow();
+        let local = |name: &String| annotated.iter().rev().find_map(|scope| scope.get(name).copied());
+        match expr {
+            ExprNode::Val(value) => *value != Value::Nil,
+            ExprNode::String(_) => true,
+            ExprNode::Var(name, ..) => local(name).unwrap_or(false),
+            ExprNode::Call(name, ..) => {
+                local(name).is_none() && self.program.func_nodes.iter()
+                    .any(|rc_func| &rc_func.name == name && rc_func.return_type.is_some_and(|ret| !ret.is_dynamic()))
+            }
+            _ => false,
+        }
+    }
+
+    fn has_call(expr: &ExprNode) -> bool {
+        let mut callees = vec![];
+        expr.collect_calls(&mut callees);
+        !callees.is_empty()
+    }
+
+    fn constant(expr: &ExprNode) -> Option<Value> {
+        match expr {
+            ExprNode::Val(Value::Func(_, _)) | ExprNode::Val(Value::Nil) => None,
+            ExprNode::Val(value) => Some(value.clone()),
+            ExprNode::String(value) => Some(Value::Chars(value.clone())),
+            _ => None,
+        }
+    }
+
+    // operands the evaluator rejects are left alone, so they still fail at runtime,
+    // operations that always fail are errors
+    fn fold_values(&self, op: &str, value_a: Value, value_b: Value, func: &String) -> Option<Value> {
+        Type::binary(op, Type::of(&value_a), Type::of(&value_b))?;
+
+        let operands = match (&value_a, &value_b) {
+            (Value::I32(a), Value::I32(b)) => Some((*a, *b)),
+            _ => None,
+        };
+        match Evaluator::apply(op, value_a, value_b, self.overflow) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                match (error.code(), operands) {
+                    ("E0505", Some((a, b))) => self.error(
+                        Diagnostic::error("E0402", format!("division by zero in constant expression '{a} / {b}' in function '{func}'"))
+                            .with_primary(self.statement.get(), "this statement always fails at runtime")),
+                    ("E0507", Some((a, b))) => self.error(
+                        Diagnostic::error("E0401", format!("constant expression '{a} {op} {b}' overflows int32 in function '{func}'"))
+                            .with_primary(self.statement.get(), "this statement always fails at runtime")),
+                    _ => {}
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::analyzer::Analyzer;
+    use crate::diagnostic::Diagnostics;
+    use crate::evaluator::Overflow;
+    use crate::lexer::Lexer;
+    use crate::parser::DescentParser;
+
+    fn optimize(source: &str) -> Result<String, String> {
+        optimize_with(source, Overflow::Error)
+    }
+
+    fn optimize_with(source: &str, overflow: Overflow) -> Result<String, String> {
+        let diagnostics = Rc::new(Diagnostics::new("<test>", source));
+        let mut parser = DescentParser::new(Lexer::new(source.to_string()), diagnostics.clone());
+        let program = Rc::new(parser.analyze());
+        let analyzer = Analyzer::new(program.clone(), diagnostics.clone());
+        analyzer.analyze()?;
+        let mut optimizer = super::Optimizer::new(program, analyzer.into_types(), diagnostics);
+        optimizer.set_overflow(overflow);
+        optimizer.optimize().map(|optimized| optimized.to_string())
+    }
+
+    // optimized body of `func f(<params>) [ return <expr>; ]`
+    fn returned(params: &str, expr: &str) -> String {
+        let source = format!("func g() -> int32 [ return 1; ] func h() -> bool [ return true; ] func f({params}) [ return {expr}; ]");
+        let optimized = optimize(&source).unwrap();
+        let start = optimized.rfind("return ").unwrap() + "return ".len();
+        let end = optimized[start..].find(';').unwrap();
+        optimized[start..start + end].to_string()
+    }
+
+    #[test]
+    fn it_folds_constant_subexpressions() {
+        let optimized = optimize("func f(x) [ return x * 2 + 3; ] func main() [ print \"While loop \" + 50; ]").unwrap();
+
+        assert!(optimized.contains("return x * 5;"));
+        assert!(optimized.contains("print \"While loop 50\";"));
+    }
+
+    #[test]
+    fn it_reports_overflow_and_division_by_zero() {
+        assert!(optimize("func main() [ print 2147483647 + 1; ]").is_err());
+        assert!(optimize("func main() [ print 1 / 0; ]").is_err());
+        assert!(optimize("func main() [ print 0 - 2147483647 - 1; ]").unwrap().contains("print -2147483646;"));
+    }
+
+    #[test]
+    fn it_folds_overflow_like_the_runtime() {
+        let source = "func main() [ print 2147483647 + 1; ]";
+        assert!(optimize_with(source, Overflow::Wrap).unwrap().contains("print -2147483648;"));
+        assert!(optimize_with(source, Overflow::Saturate).unwrap().contains("print 2147483647;"));
+        assert!(optimize_with("func main() [ print 1 / 0; ]", Overflow::Wrap).is_err());
+    }
+
+    #[test]
+    fn it_simplifies_multiplication_by_zero() {
+        assert_eq!(returned("x: int32", "x * 0"), "0");
+        assert_eq!(returned("x: int32", "0 * x"), "0");
+        assert_eq!(returned("x: int32", "g() * 0"), "g() * 0");
+    }
+
+    #[test]
+    fn it_simplifies_multiplication_by_one() {
+        assert_eq!(returned("x: int32", "x * 1"), "x");
+        assert_eq!(returned("x: int32", "1 * x"), "x");
+        assert_eq!(returned("x: int32", "g() * 1"), "g()");
+    }
+
+    #[test]
+    fn it_simplifies_adding_and_subtracting_zero() {
+        assert_eq!(returned("x: int32", "x + 0"), "x");
+        assert_eq!(returned("x: int32", "0 + x"), "x");
+        assert_eq!(returned("x: int32", "x - 0"), "x");
+        assert_eq!(returned("x: int32", "0 - x"), "0 - x");
+    }
+
+    #[test]
+    fn it_simplifies_division_by_one() {
+        assert_eq!(returned("x: int32", "x / 1"), "x");
+        assert_eq!(returned("x: int32", "1 / x"), "1 / x");
+    }
+
+    #[test]
+    fn it_keeps_identities_of_other_types() {
+        assert_eq!(returned("x: flt32", "x * 0"), "x * 0");
+        assert_eq!(returned("x: char", "x + 0"), "x + 0");
+    }
+
+    #[test]
+    fn it_simplifies_or_true() {
+        assert_eq!(returned("b: bool", "b or true"), "true");
+        assert_eq!(returned("b: bool", "true or b"), "true");
+        assert_eq!(returned("b: bool", "h() or true"), "h() or true");
+        assert_eq!(returned("b: bool", "true or h()"), "true");
+    }
+
+    #[test]
+    fn it_simplifies_and_false() {
+        assert_eq!(returned("b: bool", "b and false"), "false");
+        assert_eq!(returned("b: bool", "false and b"), "false");
+        assert_eq!(returned("b: bool", "h() and false"), "h() and false");
+    }
+
+    #[test]
+    fn it_does_not_fold_operands_that_are_never_evaluated() {
+        assert_eq!(optimize("func main() [ print false and 1 == 1 / 0; ]").map(|optimized| optimized.contains("print false;")), Ok(true));
+        assert_eq!(optimize("func main() [ print true or 1 == 2147483647 + 1; ]").map(|optimized| optimized.contains("print true;")), Ok(true));
+        assert!(optimize("func main() [ print true and 1 == 1 / 0; ]").is_err());
+    }
+
+    #[test]
+    fn it_simplifies_neutral_bool_operands() {
+        assert_eq!(returned("b: bool", "b or false"), "b");
+        assert_eq!(returned("b: bool", "false or b"), "b");
+        assert_eq!(returned("b: bool", "b and true"), "b");
+        assert_eq!(returned("b: bool", "true and h()"), "h()");
+    }
+
+    #[test]
+    fn it_keeps_identities_of_unannotated_operands() {
+        // 'k' and 'b' end up holding a string and an int at runtime, so these operations fail
+        let source = "let k; let b; func set() [ k = 1; k = \"k\"; b = true; b = 1; ] func get() [ return k; ]
+            func main() [ let s; s = set(); print k * 1; print get() + 0; print b or false; ]";
+        let optimized = optimize(source).unwrap();
+
+        assert!(optimized.contains("print k * 1;"));
+        assert!(optimized.contains("print get() + 0;"));
+        assert!(optimized.contains("print b or false;"));
+        assert_eq!(returned("x", "x * 0"), "x * 0");
+        assert_eq!(returned("b", "true and b"), "true and b");
+    }
+
+    #[test]
+    fn it_keeps_identities_of_variables_assigned_at_runtime() {
+        // only arguments are checked against their annotation, assigned values are not
+        let optimized = optimize("func f(x: int32) [ let y: int32 = 1; x = 2; print y * 0; return x * 0; ]").unwrap();
+
+        assert!(optimized.contains("print y * 0;"));
+        assert!(optimized.contains("return x * 0;"));
+    }
+
+    #[test]
+    fn it_simplifies_double_negation() {
+        assert_eq!(returned("b: bool", "not not b"), "b");
+        assert_eq!(returned("b: bool", "not not h()"), "h()");
+        assert_eq!(returned("b: bool", "not true"), "false");
+    }
+}
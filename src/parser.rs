@@ -2,26 +2,41 @@
 #![allow(non_snake_case)]
 #![allow(dead_code)]
 
+use std::cell::Cell;
 use std::rc::Rc;
 
+use crate::diagnostic::{Diagnostic, Diagnostics};
 use crate::lexer::Lexer;
 use crate::logger::Logger;
+use crate::span::Span;
+use crate::suggest;
 use crate::token::Token;
 use crate::tree::*;
+use crate::types::Type;
 use crate::value::Value;
 
 const INDENT: usize = 2;
 
+// a syntax error, reported once the parser has recovered from it
+type ParseResult<T> = Result<T, Diagnostic>;
+
 pub struct DescentParser {
     lexer: Lexer,
     indent: usize,
+    // location of the last consumed token
+    previous: Span,
+    previous_token: Token,
+    diagnostics: Rc<Diagnostics>,
 }
 
 impl DescentParser {
-    pub fn new(lexer: Lexer) -> DescentParser {
+    pub fn new(lexer: Lexer, diagnostics: Rc<Diagnostics>) -> DescentParser {
         DescentParser {
             lexer,
             indent: 0,
+            previous: Span::default(),
+            previous_token: Token::UNDEFINED,
+            diagnostics,
         }
     }
 
@@ -33,53 +48,108 @@ impl DescentParser {
 
         // parse lexer output until we reach the EOI token
         while !self.peek(Token::EOI) {
-            match self.curr() {
+            let indent = self.indent;
+            let result = match self.curr() {
                 Token::KW_FUNC => {
-                    let func_node = self.parse_func();
-                    program.func_nodes.push(Rc::new(func_node));
+                    self.parse_func().map(|func_node| program.func_nodes.push(Rc::new(func_node)))
                 }
-                Token::KW_LET => {
-                    let let_node = self.parse_let();
-                    program.let_nodes.push(Rc::new(let_node));
+                Token::HASH_SIGN => {
+                    self.parse_attributed_func().map(|func_node| program.func_nodes.push(Rc::new(func_node)))
                 }
-                _ => {
-                    panic!("Unexpected token `{}` while parsing program.", self.curr())
+                Token::KW_LET => {
+                    self.parse_let().map(|let_node| program.let_nodes.push(Rc::new(let_node)))
                 }
+                _ => Err(self.unexpected("a function or global variable")),
+            };
+            if let Err(diagnostic) = result {
+                self.diagnostics.report(diagnostic);
+                self.indent = indent;
+                self.synchronize(&[Token::KW_FUNC, Token::KW_LET, Token::HASH_SIGN]);
             }
         }
 
-        self.expect(Token::EOI);
-
         program
     }
 
     /*
     * EBNF
-    * func = 'func' <identifier> '(' <parameter_list> ')' '->' <type> <block_nest>
+    * func = 'func' <identifier> <parameter_list> ['->' <type>] <block_nest>
     * identifier = ID(String)
-    * type = TYPE_INT32 | TYPE_FLT32 | TYPE_CHAR
+    * type = TYPE_INT32 | TYPE_FLT32 | TYPE_CHAR | TYPE_BOOL
     */
-    fn parse_func(&mut self) -> FuncNode {
+    fn parse_func(&mut self) -> ParseResult<FuncNode> {
         self.indent_print("parse_func()");
         self.indent_increment();
 
-        self.expect(Token::KW_FUNC);
+        self.expect(Token::KW_FUNC)?;
 
-        let func_name = self.expect(Token::id());
+        let name_span = self.span();
+        let func_name = self.expect(Token::id())?;
 
-        let params_node = self.parse_parameter_list();
+        let params_node = self.parse_parameter_list()?;
 
-        // optional return type todo: implement
-        // if self.accept(Token::ARROW_R) {
-        //     let return_node = self.help_parse_type();
-        //     func_node.push(return_node);
-        // }
+        // optional return type
+        let mut return_type = None;
+        if self.accept(Token::ARROW_R) {
+            return_type = Some(self.help_parse_type()?);
+        }
 
-        let block_node = self.parse_block_nest();
+        let block_node = self.parse_block_nest()?;
 
         self.indent_decrement();
 
-        FuncNode::new(func_name.get_id_name(), params_node, block_node)
+        Ok(FuncNode::new(func_name.get_id_name(), params_node, return_type, block_node, name_span))
+    }
+
+    /*
+    * EBNF
+    * attributed_func = <attribute> {<attribute>} <func>
+    */
+    fn parse_attributed_func(&mut self) -> ParseResult<FuncNode> {
+        self.indent_print("parse_attributed_func()");
+        self.indent_increment();
+
+        let mut attributes = vec![];
+        while self.peek(Token::HASH_SIGN) {
+            attributes.push(self.parse_attribute()?);
+        }
+
+        let mut func_node = self.parse_func()?;
+        func_node.attributes = attributes;
+
+        self.indent_decrement();
+        Ok(func_node)
+    }
+
+    /*
+    * EBNF
+    * attribute = '#' '[' <identifier> '(' <identifier> {',' <identifier>} ')' ']'
+    * identifier = ID(String)
+    */
+    fn parse_attribute(&mut self) -> ParseResult<Attribute> {
+        self.indent_print("parse_attribute()");
+        self.indent_increment();
+
+        let start = self.span();
+        self.expect(Token::HASH_SIGN)?;
+        self.expect(Token::BRACKET_L)?;
+        let name = self.expect(Token::id())?;
+
+        self.expect(Token::PARENS_L)?;
+        let mut args = vec![];
+        loop {
+            let arg_span = self.span();
+            let arg = self.expect(Token::id())?;
+            args.push((arg.get_id_name(), arg_span));
+            if !self.accept(Token::COMMA) {
+                break;
+            }
+        }
+        self.expect(Token::PARENS_R)?;
+        self.expect(Token::BRACKET_R)?;
+
+        self.indent_decrement();
+        Ok(Attribute::new(name.get_id_name(), args, start.to(self.previous)))
     }
 
     /*
@@ -89,18 +159,18 @@ impl DescentParser {
     * identifier = ID(String)
     * type = TYPE_INT32 | TYPE_FLT32 | TYPE_CHAR | TYPE_BOOL
     */
-    fn parse_parameter_list(&mut self) -> Vec<Parameter> {
+    fn parse_parameter_list(&mut self) -> ParseResult<Vec<Parameter>> {
         self.indent_print("parse_parameter_list()");
         self.indent_increment();
         let mut params = vec![];
 
-        self.expect(Token::PARENS_L);
+        self.expect(Token::PARENS_L)?;
         if self.accept(Token::PARENS_R) {
-            return params;
+            return Ok(params);
         }
 
         loop {
-            let parameter = self.parse_parameter();
+            let parameter = self.parse_parameter()?;
             params.push(parameter);
 
             if !self.accept(Token::COMMA) {
@@ -108,152 +178,139 @@ impl DescentParser {
             }
         }
 
-        self.expect(Token::PARENS_R);
+        self.expect(Token::PARENS_R)?;
 
         self.indent_decrement();
-        params
+        Ok(params)
     }
 
     /*
     * EBNF
-    * parameter = <identifier> ':' <type>
+    * parameter = <identifier> ':' <type> | <identifier>
     * identifier = ID(String)
-    * type = TYPE_INT32 | TYPE_FLT32 | TYPE_CHAR
+    * type = TYPE_INT32 | TYPE_FLT32 | TYPE_CHAR | TYPE_BOOL
     */
-    fn parse_parameter(&mut self) -> Parameter {
+    fn parse_parameter(&mut self) -> ParseResult<Parameter> {
         self.indent_print("parse_parameter()");
         self.indent_increment();
 
-        let param_name = self.expect(Token::id());
+        let param_span = self.span();
+        let param_name = self.expect(Token::id())?;
 
-        // todo: add parameter types
-        // self.expect(Token::COLON);
-        //
-        // // match the type
-        // let type_node = self.help_parse_type();
-        // param_node.push(type_node);
+        // optional parameter type
+        let mut param_type = None;
+        if self.accept(Token::COLON) {
+            param_type = Some(self.help_parse_type()?);
+        }
 
         self.indent_decrement();
-        Parameter::new(param_name.get_id_name())
+        Ok(Parameter::new(param_name.get_id_name(), param_type, param_span))
     }
 
     /*
     * EBNF
     * type = TYPE_INT32 | TYPE_FLT32 | TYPE_CHAR | TYPE_BOOL
     */
-    // todo: reimpl
-    // fn help_parse_type(&mut self) -> ParseTree {
-    //     let type_node = match self.curr() {
-    //         Token::TYPE_INT32 => {
-    //             self.expect(Token::TYPE_INT32);
-    //             ParseTree::new(Token::TYPE_INT32)
-    //         }
-    //         Token::TYPE_FLT32 => {
-    //             self.expect(Token::TYPE_FLT32);
-    //             ParseTree::new(Token::TYPE_FLT32)
-    //         }
-    //         Token::TYPE_CHAR => {
-    //             self.expect(Token::TYPE_CHAR);
-    //             ParseTree::new(Token::TYPE_CHAR)
-    //         }
-    //         Token::TYPE_BOOL => {
-    //             self.expect(Token::TYPE_BOOL);
-    //             ParseTree::new(Token::TYPE_BOOL)
-    //         }
-    //         _ => panic!("Expected type but found '{:?}'", self.curr()),
-    //     };
-    //
-    //     type_node
-    // }
+    fn help_parse_type(&mut self) -> ParseResult<Type> {
+        self.indent_print("help_parse_type()");
+
+        let token = self.curr();
+        let type_node = match token {
+            Token::TYPE_INT32 => Type::Int32,
+            Token::TYPE_FLT32 => Type::Flt32,
+            Token::TYPE_CHAR => Type::Char,
+            Token::TYPE_BOOL => Type::Bool,
+            _ => return Err(self.unexpected("a type")),
+        };
+        self.expect(token)?;
+
+        Ok(type_node)
+    }
 
     /*
     * EBNF
     * block_nest = '[' <block_list> ']'
     * block_list = <block_nest> | <let> <block_list> |
     */
-    fn parse_block_nest(&mut self) -> BlockNode {
+    fn parse_block_nest(&mut self) -> ParseResult<BlockNode> {
         self.indent_print("parse_block_nest()");
         self.indent_increment();
         let mut block_node = BlockNode::new();
 
-        self.expect(Token::BRACKET_L);
-        while !self.peek(Token::BRACKET_R) {
-            match self.curr() {
-                // Token::BRACKET_L => {
-                //     let nested_block = self.parse_block_nest();
-                //     block_node.statements.push(Rc::new(StmtNode::Block(nested_block))); todo: there is no StmtNode::Block
-                // }
-                Token::KW_LET => {
-                    let let_node = self.parse_let();
-                    block_node.statements.push(Rc::new(StmtNode::Let(let_node)));
-                }
-                Token::KW_IF => {
-                    let if_node = self.parse_if_then_else();
-                    block_node.statements.push(Rc::new(StmtNode::IfElse(if_node)))
-                }
-                Token::KW_RETURN => {
-                    let return_node = self.parse_return();
-                    block_node.statements.push(Rc::new(StmtNode::Return(return_node)))
-                }
-                Token::KW_WHILE => {
-                    let while_node = self.parse_while();
-                    block_node.statements.push(Rc::new(StmtNode::While(while_node)))
+        self.expect(Token::BRACKET_L)?;
+        while !self.peek(Token::BRACKET_R) && !self.peek(Token::EOI) {
+            let indent = self.indent;
+            match self.parse_statement() {
+                Ok(stmt_node) => block_node.statements.push(Rc::new(stmt_node)),
+                Err(diagnostic) => {
+                    // skip the broken statement and continue with the next one
+                    self.diagnostics.report(diagnostic);
+                    self.indent = indent;
+                    self.synchronize(&[]);
                 }
-                Token::KW_PRINT => {
-                    let print_node = self.parse_print();
-                    block_node.statements.push(Rc::new(StmtNode::Print(print_node)))
-                }
-                Token::ID(_) => {
-                    let assign_node = self.parse_assign();
-                    block_node.statements.push(Rc::new(StmtNode::Assign(assign_node)))
-                }
-                _ => panic!("Unexpected token in block: '{:?}'", self.curr()),
             }
         }
-        self.expect(Token::BRACKET_R);
+        self.expect(Token::BRACKET_R)?;
 
         self.indent_decrement();
-        block_node
+        Ok(block_node)
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<StmtNode> {
+        let stmt_node = match self.curr() {
+            // Token::BRACKET_L => {
+            //     let nested_block = self.parse_block_nest();
+            //     block_node.statements.push(Rc::new(StmtNode::Block(nested_block))); todo: there is no StmtNode::Block
+            // }
+            Token::KW_LET => StmtNode::Let(self.parse_let()?),
+            Token::KW_IF => StmtNode::IfElse(self.parse_if_then_else()?),
+            Token::KW_RETURN => StmtNode::Return(self.parse_return()?),
+            Token::KW_WHILE => StmtNode::While(self.parse_while()?),
+            Token::KW_PRINT => StmtNode::Print(self.parse_print()?),
+            Token::ID(_) => StmtNode::Assign(self.parse_assign()?),
+            _ => return Err(self.unexpected("a statement")),
+        };
+        Ok(stmt_node)
     }
 
     /*
     * EBNF
-    * let = 'let' <identifier> ':' <type> '=' <value>';' | 'let' <identifier> ':' <type>';' | 'let' <identifier>';'
+    * let = 'let' <identifier> [':' <type>] ['=' <value>] ';'
     * identifier = ID(String)
-    * type = TYPE_INT32 | TYPE_FLT32 | TYPE_CHAR
+    * type = TYPE_INT32 | TYPE_FLT32 | TYPE_CHAR | TYPE_BOOL
     * expression = <term> <expression_tail>
     * term = <factor> <term_tail>
     * value = <identifier> | <literal>
     * literal = LIT_INT32(i32) | LIT_FLT32(f32) | LIT_CHAR(char) | LIT_STRING(String)
     */
-    fn parse_let(&mut self) -> LetNode {
+    fn parse_let(&mut self) -> ParseResult<LetNode> {
         self.indent_print("parse_let()");
         self.indent_increment();
 
+        let start = self.span();
+        self.expect(Token::KW_LET)?;
+        let let_name = self.expect(Token::id())?;
 
-        self.expect(Token::KW_LET);
-        let let_name = self.expect(Token::id());
+        // optional type annotation
+        let mut let_type = None;
+        if self.accept(Token::COLON) {
+            let_type = Some(self.help_parse_type()?);
+        }
 
-        // if self.accept(Token::COLON) {
-        //     // match the type
-        //     let let_type = self.help_parse_type();
-        //     let_node.push(let_type);
-        // }
-        // todo: add value assignment
         // optional assignment
         let mut val_node = Value::Nil;
         if self.accept(Token::OP_ASSIGN) {
-            let expr_node = self.parse_expr();
+            let expr_node = self.parse_expr()?;
             val_node = match expr_node {
                 ExprNode::Val(val) => val,
-                _ => panic!("Expected value but found '{:?}'", self.curr()),
+                _ => return Err(self.unexpected("a literal value")),
             };
         }
 
-        self.expect(Token::SEMICOLON);
+        self.expect(Token::SEMICOLON)?;
 
         self.indent_decrement();
-        LetNode::new(let_name.get_id_name(), val_node)
+        Ok(LetNode::new(let_name.get_id_name(), let_type, val_node, start.to(self.previous)))
     }
 
     /*
@@ -262,24 +319,28 @@ impl DescentParser {
     * bool = true | false
     * block_nest = '[' <block_list> ']'
     */
-    fn parse_if_then_else(&mut self) -> IfElseNode {
+    fn parse_if_then_else(&mut self) -> ParseResult<IfElseNode> {
         self.indent_print("parse_if_then_else()");
         self.indent_increment();
 
-        self.expect(Token::KW_IF);
+        let start = self.span();
+        self.expect(Token::KW_IF)?;
 
-        let condition_expr = self.parse_expr();
+        let condition_start = self.span();
+        let condition_expr = self.parse_expr()?;
+        let condition_span = condition_start.to(self.previous);
+        let span = start.to(self.previous);
 
-        let then_node_block = self.parse_block_nest();
+        let then_node_block = self.parse_block_nest()?;
 
         // optional else block
         let mut else_node_block: Option<BlockNode> = None;
         if self.accept(Token::KW_ELSE) {
-            else_node_block = self.parse_block_nest().into();
+            else_node_block = self.parse_block_nest()?.into();
         }
 
         self.indent_decrement();
-        IfElseNode::new(condition_expr, then_node_block, else_node_block)
+        Ok(IfElseNode::new(condition_expr, condition_span, then_node_block, else_node_block, span))
     }
 
     /*
@@ -289,53 +350,60 @@ impl DescentParser {
     * identifier = ID(String)
     * literal = LIT_INT32(i32) | LIT_FLT32(f32) | LIT_CHAR(char) | LIT_STRING(String) | LIT_BOOL(bool)
     */
-    fn parse_return(&mut self) -> ReturnNode {
+    fn parse_return(&mut self) -> ParseResult<ReturnNode> {
         self.indent_print("parse_return()");
         self.indent_increment();
 
-        self.expect(Token::KW_RETURN);
-        let expr_node = self.parse_expr();
-        self.expect(Token::SEMICOLON);
+        let start = self.span();
+        self.expect(Token::KW_RETURN)?;
+        let expr_node = self.parse_expr()?;
+        self.expect(Token::SEMICOLON)?;
 
         self.indent_decrement();
-        ReturnNode::new(expr_node)
+        Ok(ReturnNode::new(expr_node, start.to(self.previous)))
     }
 
-    fn parse_while(&mut self) -> WhileNode {
+    fn parse_while(&mut self) -> ParseResult<WhileNode> {
         self.indent_print("parse_while()");
         self.indent_increment();
 
-        self.expect(Token::KW_WHILE);
-        let expr_node = self.parse_expr();
-        let block_node = self.parse_block_nest();
+        let start = self.span();
+        self.expect(Token::KW_WHILE)?;
+        let condition_start = self.span();
+        let expr_node = self.parse_expr()?;
+        let condition_span = condition_start.to(self.previous);
+        let span = start.to(self.previous);
+        let block_node = self.parse_block_nest()?;
 
         self.indent_decrement();
-        WhileNode::new(expr_node, block_node)
+        Ok(WhileNode::new(expr_node, condition_span, block_node, span))
     }
 
-    fn parse_print(&mut self) -> PrintNode {
+    fn parse_print(&mut self) -> ParseResult<PrintNode> {
         self.indent_print("parse_print()");
         self.indent_increment();
 
-        self.expect(Token::KW_PRINT);
-        let expr_node = self.parse_expr();
-        self.expect(Token::SEMICOLON);
+        let start = self.span();
+        self.expect(Token::KW_PRINT)?;
+        let expr_node = self.parse_expr()?;
+        self.expect(Token::SEMICOLON)?;
 
         self.indent_decrement();
-        PrintNode::new(expr_node)
+        Ok(PrintNode::new(expr_node, start.to(self.previous)))
     }
 
-    fn parse_assign(&mut self) -> AssignNode {
+    fn parse_assign(&mut self) -> ParseResult<AssignNode> {
         self.indent_print("parse_assign()");
         self.indent_increment();
 
-        let id_node = self.expect(Token::id());
-        self.expect(Token::OP_ASSIGN);
-        let expr_node = self.parse_expr();
-        self.expect(Token::SEMICOLON);
+        let start = self.span();
+        let id_node = self.expect(Token::id())?;
+        self.expect(Token::OP_ASSIGN)?;
+        let expr_node = self.parse_expr()?;
+        self.expect(Token::SEMICOLON)?;
 
         self.indent_decrement();
-        AssignNode::new(id_node.get_id_name(), expr_node)
+        Ok(AssignNode::new(id_node.get_id_name(), expr_node, start.to(self.previous)))
     }
 
     /*
@@ -346,41 +414,49 @@ impl DescentParser {
     */
     //todo: this is very basic, just a temp as we have no pratt parser
 
-    fn parse_expr(&mut self) -> ExprNode {
+    fn parse_expr(&mut self) -> ParseResult<ExprNode> {
         self.indent_print("parse_expr()");
         self.indent_increment();
         let token = self.curr();
 
         let expr_node = match token {
+            // 'not' applies to the whole rest of the expression, like every operator here
+            Token::OP_NOT => {
+                self.expect(Token::OP_NOT)?;
+                let operand = self.parse_expr()?;
+                self.indent_decrement();
+                return Ok(ExprNode::Not(Rc::new(operand)));
+            }
             Token::ID(_) => {
-                let id_node = self.expect(Token::id());
+                let span = self.span();
+                let id_node = self.expect(Token::id())?;
                 if self.peek(Token::PARENS_L) {
-                    self.parse_func_call(id_node.get_id_name())
+                    self.parse_func_call(id_node.get_id_name(), span)?
                 } else {
-                    ExprNode::Var(id_node.get_id_name())
+                    ExprNode::Var(id_node.get_id_name(), span, Cell::new(None))
                 }
             }
             Token::LIT_INT32(_) => {
-                let lit_node = self.expect(Token::lit_i32());
+                let lit_node = self.expect(Token::lit_i32())?;
                 ExprNode::Val(Value::I32(lit_node.get_lit_i32()))
             }
             Token::LIT_FLT32(_) => {
-                let lit_node = self.expect(Token::lit_f32());
+                let lit_node = self.expect(Token::lit_f32())?;
                 ExprNode::Val(Value::F32(lit_node.get_lit_f32()))
             }
             Token::LIT_CHAR(_) => {
-                let lit_node = self.expect(Token::lit_char());
+                let lit_node = self.expect(Token::lit_char())?;
                 ExprNode::Val(Value::Chars(lit_node.get_lit_char().to_string())) // todo: fix this
             }
             Token::LIT_STRING(_) => {
-                let lit_node = self.expect(Token::lit_string());
+                let lit_node = self.expect(Token::lit_string())?;
                 ExprNode::Val(Value::Chars(lit_node.get_lit_string()))
             }
             Token::LIT_BOOL(_) => {
-                let lit_node = self.expect(Token::lit_bool());
+                let lit_node = self.expect(Token::lit_bool())?;
                 ExprNode::Val(Value::Bool(lit_node.get_lit_bool()))
             }
-            _ => panic!("Expected value but found '{:?}'", self.curr()),
+            _ => return Err(self.unexpected("an expression")),
         };
 
         let is_end_of_expr = match self.curr() {
@@ -393,66 +469,81 @@ impl DescentParser {
 
         if is_end_of_expr {
             self.indent_decrement();
-            return expr_node;
+            return Ok(expr_node);
         }
 
-        let expr_tail_node = self.parse_expr_tail(expr_node);
+        let expr_tail_node = self.parse_expr_tail(expr_node)?;
         self.indent_decrement();
-        expr_tail_node
+        Ok(expr_tail_node)
     }
 
-    fn parse_expr_tail(&mut self, left_denotation: ExprNode) -> ExprNode {
+    fn parse_expr_tail(&mut self, left_denotation: ExprNode) -> ParseResult<ExprNode> {
         self.indent_print("parse_expr_tail()");
         self.indent_increment();
         let token = self.curr();
 
         let expr_node = match token {
             Token::OP_ADD => {
-                self.expect(Token::OP_ADD);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_ADD)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::Add(Rc::new(left_denotation), Rc::new(right_denotation))
             }
             Token::OP_MUL => {
-                self.expect(Token::OP_MUL);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_MUL)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::Mul(Rc::new(left_denotation), Rc::new(right_denotation))
             }
+            Token::OP_DIV => {
+                self.expect(Token::OP_DIV)?;
+                let right_denotation = self.parse_expr()?;
+                ExprNode::Div(Rc::new(left_denotation), Rc::new(right_denotation))
+            }
             Token::OP_SUB => {
-                self.expect(Token::OP_SUB);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_SUB)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::Sub(Rc::new(left_denotation), Rc::new(right_denotation))
             }
             Token::OP_LT => {
-                self.expect(Token::OP_LT);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_LT)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::LessThan(Rc::new(left_denotation), Rc::new(right_denotation))
             }
             Token::OP_GT => {
-                self.expect(Token::OP_GT);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_GT)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::GreaterThan(Rc::new(left_denotation), Rc::new(right_denotation))
             }
             Token::OP_EQ => {
-                self.expect(Token::OP_EQ);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_EQ)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::EqualTo(Rc::new(left_denotation), Rc::new(right_denotation))
             }
             Token::OP_NGT => {
-                self.expect(Token::OP_NGT);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_NGT)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::LessThanEq(Rc::new(left_denotation), Rc::new(right_denotation))
             }
             Token::OP_NLT => {
-                self.expect(Token::OP_NLT);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_NLT)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::GreaterThanEq(Rc::new(left_denotation), Rc::new(right_denotation))
             }
             Token::OP_NEQ => {
-                self.expect(Token::OP_NEQ);
-                let right_denotation = self.parse_expr();
+                self.expect(Token::OP_NEQ)?;
+                let right_denotation = self.parse_expr()?;
                 ExprNode::NotEqualTo(Rc::new(left_denotation), Rc::new(right_denotation))
             }
-            _ => panic!("Expected value but found '{:?}'", self.curr()),
+            Token::OP_AND => {
+                self.expect(Token::OP_AND)?;
+                let right_denotation = self.parse_expr()?;
+                ExprNode::And(Rc::new(left_denotation), Rc::new(right_denotation))
+            }
+            Token::OP_OR => {
+                self.expect(Token::OP_OR)?;
+                let right_denotation = self.parse_expr()?;
+                ExprNode::Or(Rc::new(left_denotation), Rc::new(right_denotation))
+            }
+            _ => return Err(self.unexpected("';' or an operator")),
         };
 
         let is_end_of_expr = match self.curr() {
@@ -465,26 +556,26 @@ impl DescentParser {
 
         if is_end_of_expr {
             self.indent_decrement();
-            return expr_node;
+            return Ok(expr_node);
         }
 
-        let expr_tail_node = self.parse_expr_tail(expr_node);
+        let expr_tail_node = self.parse_expr_tail(expr_node)?;
         self.indent_decrement();
-        expr_tail_node
+        Ok(expr_tail_node)
     }
 
-    fn parse_func_call(&mut self, func_name: String) -> ExprNode {
-        self.expect(Token::PARENS_L);
+    fn parse_func_call(&mut self, func_name: String, span: Span) -> ParseResult<ExprNode> {
+        self.expect(Token::PARENS_L)?;
         let mut args = vec![];
         while !self.peek(Token::PARENS_R) {
-            let arg = self.parse_expr();
+            let arg = self.parse_expr()?;
             args.push(Rc::new(arg));
             if !self.accept(Token::COMMA) {
                 break;
             }
         }
-        self.expect(Token::PARENS_R);
-        ExprNode::Call(func_name, args)
+        self.expect(Token::PARENS_R)?;
+        Ok(ExprNode::Call(func_name, args, span.to(self.previous), Cell::new(None)))
     }
 }
 
@@ -495,17 +586,70 @@ impl DescentParser {
     }
 
     fn advance(&mut self) {
+        self.previous = self.lexer.span();
+        self.previous_token = self.lexer.current();
         self.lexer.advance();
+        for diagnostic in self.lexer.take_errors() {
+            self.diagnostics.report(diagnostic);
+        }
     }
 
-    fn expect(&mut self, expected: Token) -> Token {
+    fn span(&self) -> Span {
+        self.lexer.span()
+    }
+
+    fn expect(&mut self, expected: Token) -> ParseResult<Token> {
         if self.curr() == expected {
             let curr = self.curr().clone();
             Logger::debug(&format!("{:<indent$}expect({:?})", "", curr, indent = self.indent));
             self.advance();
-            curr
+            Ok(curr)
         } else {
-            panic!("Expected '{:?}' but found '{:?}'", expected, self.curr());
+            let expected = match expected {
+                Token::ID(_) => "an identifier".to_string(),
+                _ => format!("'{}'", expected),
+            };
+            Err(self.unexpected(&expected))
+        }
+    }
+
+    fn unexpected(&mut self, expected: &str) -> Diagnostic {
+        let found = match self.curr() {
+            Token::EOI => "end of input".to_string(),
+            token => format!("'{}'", token),
+        };
+        let diagnostic = Diagnostic::error("E0001", format!("expected {expected}, found {found}"))
+            .with_primary(self.span(), &format!("expected {expected}"));
+
+        // a misspelled keyword is read as identifier, often the error is only found at the next token
+        for token in [self.curr(), self.previous_token.clone()] {
+            if let Token::ID(name) = token {
+                if let Some(keyword) = suggest::keyword(&name) {
+                    return diagnostic.with_help(format!("'{name}' is not a keyword, did you mean '{keyword}'?"));
+                }
+            }
+        }
+        diagnostic
+    }
+
+    // skips to the end of the broken statement, or up to one of the `stop` tokens outside of brackets
+    fn synchronize(&mut self, stop: &[Token]) {
+        let mut depth = 0;
+        loop {
+            match self.curr() {
+                Token::EOI => return,
+                Token::BRACKET_L => depth += 1,
+                // the enclosing block ends, statements stop in front of it
+                Token::BRACKET_R if depth == 0 && stop.is_empty() => return,
+                Token::BRACKET_R if depth > 0 => depth -= 1,
+                Token::SEMICOLON if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                token if depth == 0 && stop.contains(&token) => return,
+                _ => {}
+            }
+            self.advance();
         }
     }
 
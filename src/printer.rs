@@ -0,0 +1,138 @@
+use std::fmt;
+use std::fmt::Display;
+
+use crate::tree::{BlockNode, ExprNode, FuncNode, LetNode, ProgramNode, StmtNode};
+use crate::value::Value;
+
+// prints the tree back as source code, used to dump the optimized program
+
+impl Display for ProgramNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rc_let in &self.let_nodes {
+            writeln!(f, "{}", rc_let)?;
+        }
+        for rc_func in &self.func_nodes {
+            writeln!(f)?;
+            write!(f, "{}", rc_func)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for FuncNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for attribute in &self.attributes {
+            let args: Vec<&str> = attribute.args.iter().map(|(arg, _)| arg.as_str()).collect();
+            writeln!(f, "#[{}({})]", attribute.name, args.join(", "))?;
+        }
+        let params: Vec<String> = self.parameters.iter()
+            .map(|param| match &param.param_type {
+                Some(param_type) => format!("{}: {}", param.name, param_type),
+                None => param.name.clone(),
+            })
+            .collect();
+        write!(f, "func {}({})", self.name, params.join(", "))?;
+        if let Some(return_type) = &self.return_type {
+            write!(f, " -> {}", return_type)?;
+        }
+        write_block(f, &self.block_node, 0)?;
+        writeln!(f)
+    }
+}
+
+impl Display for LetNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "let {}", self.name)?;
+        if let Some(let_type) = &self.let_type {
+            write!(f, ": {}", let_type)?;
+        }
+        if self.value != Value::Nil {
+            write!(f, " = {}", Literal(&self.value))?;
+        }
+        write!(f, ";")
+    }
+}
+
+impl Display for ExprNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprNode::Var(name, ..) => write!(f, "{}", name),
+            ExprNode::Val(value) => write!(f, "{}", Literal(value)),
+            ExprNode::String(value) => write!(f, "\"{}\"", value),
+            ExprNode::Call(name, args, ..) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            ExprNode::Add(a, b) => write_binary(f, "+", a, b),
+            ExprNode::Sub(a, b) => write_binary(f, "-", a, b),
+            ExprNode::Mul(a, b) => write_binary(f, "*", a, b),
+            ExprNode::Div(a, b) => write_binary(f, "/", a, b),
+            ExprNode::LessThan(a, b) => write_binary(f, "<", a, b),
+            ExprNode::GreaterThan(a, b) => write_binary(f, ">", a, b),
+            ExprNode::EqualTo(a, b) => write_binary(f, "==", a, b),
+            ExprNode::LessThanEq(a, b) => write_binary(f, "<=", a, b),
+            ExprNode::GreaterThanEq(a, b) => write_binary(f, ">=", a, b),
+            ExprNode::NotEqualTo(a, b) => write_binary(f, "!=", a, b),
+            ExprNode::And(a, b) => write_binary(f, "and", a, b),
+            ExprNode::Or(a, b) => write_binary(f, "or", a, b),
+            ExprNode::Not(a) => write!(f, "not {}", a),
+        }
+    }
+}
+
+struct Literal<'a>(&'a Value);
+
+impl Display for Literal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::I32(i) => write!(f, "{}", i),
+            // debug format keeps the decimal point of whole numbers
+            Value::F32(x) => write!(f, "{:?}", x),
+            Value::Chars(s) => write!(f, "\"{}\"", s),
+            Value::Func(func, _) => write!(f, "{}", func.name),
+        }
+    }
+}
+
+fn write_binary(f: &mut fmt::Formatter, op: &str, a: &ExprNode, b: &ExprNode) -> fmt::Result {
+    // the parser nests binary expressions to the right, so only a left operand needs parentheses
+    if is_binary(a) {
+        write!(f, "({}) {} {}", a, op, b)
+    } else {
+        write!(f, "{} {} {}", a, op, b)
+    }
+}
+
+fn is_binary(expr: &ExprNode) -> bool {
+    !matches!(expr, ExprNode::Var(..) | ExprNode::Val(_) | ExprNode::String(_) | ExprNode::Call(..))
+}
+
+fn write_block(f: &mut fmt::Formatter, block: &BlockNode, indent: usize) -> fmt::Result {
+    writeln!(f, " [")?;
+    for rc_stmt in &block.statements {
+        write!(f, "{:indent$}", "", indent = indent + 4)?;
+        match rc_stmt.as_ref() {
+            StmtNode::Let(letNode) => writeln!(f, "{}", letNode)?,
+            StmtNode::Assign(assignNode) => writeln!(f, "{} = {};", assignNode.name, assignNode.expr)?,
+            StmtNode::Return(returnNode) => writeln!(f, "return {};", returnNode.expr)?,
+            StmtNode::Print(printNode) => writeln!(f, "print {};", printNode.expr)?,
+            StmtNode::While(whileNode) => {
+                write!(f, "while {}", whileNode.condition)?;
+                write_block(f, &whileNode.body, indent + 4)?;
+                writeln!(f)?;
+            }
+            StmtNode::IfElse(ifNode) => {
+                write!(f, "if {}", ifNode.condition)?;
+                write_block(f, &ifNode.ifBody, indent + 4)?;
+                if let Some(elseBody) = &ifNode.elseBody {
+                    write!(f, " else")?;
+                    write_block(f, elseBody, indent + 4)?;
+                }
+                writeln!(f)?;
+            }
+        }
+    }
+    write!(f, "{:indent$}]", "", indent = indent)
+}
@@ -0,0 +1,159 @@
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+
+/// A script function that was running when the error occurred, with the location it was at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub func: String,
+    pub span: Span,
+}
+
+/// Error that stops the execution of a program. It unwinds as a value through the evaluator
+/// and the executor, which record the script functions it passes on its way out to main.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    diagnostic: Box<Diagnostic>,
+    // label of the location, for errors that are created without one
+    label: &'static str,
+    // where the innermost function still on the way out was, once known
+    location: Option<Span>,
+    // innermost call first
+    pub stack: Vec<Call>,
+}
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
+
+impl RuntimeError {
+    pub fn new(diagnostic: Diagnostic) -> RuntimeError {
+        RuntimeError {
+            location: diagnostic.span(),
+            diagnostic: Box::new(diagnostic),
+            label: "error occurred here",
+            stack: vec![],
+        }
+    }
+
+    pub fn with_label(mut self, label: &'static str) -> RuntimeError {
+        self.label = label;
+        self
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.diagnostic.code
+    }
+
+    /// Locates the error at the given span unless a more precise location is known already.
+    pub fn at(mut self, span: Span) -> RuntimeError {
+        if self.location.is_none() {
+            self.location = Some(span);
+            if self.diagnostic.labels.is_empty() {
+                self.diagnostic = Box::new(self.diagnostic.with_primary(span, self.label));
+            }
+        }
+        self
+    }
+
+    /// Records that the error left the given function, the caller locates it at its call.
+    pub fn leave(mut self, func: &str, span: Span) -> RuntimeError {
+        let span = self.location.take().unwrap_or(span);
+        self.stack.push(Call { func: func.to_string(), span });
+        self
+    }
+
+    /// The error with its stack trace as notes, most recent call first. Calls that repeat,
+    /// like those of a runaway recursion, are shown once with the number of repetitions.
+    pub fn into_diagnostic(self) -> Diagnostic {
+        let mut diagnostic = *self.diagnostic;
+        let mut i = 0;
+        while i < self.stack.len() {
+            let (period, times) = repetition(&self.stack[i..]);
+            for (j, call) in self.stack[i..i + period].iter().enumerate() {
+                let note = match i + j {
+                    0 => format!("in function '{}' at {}", call.func, call.span),
+                    _ => format!("called from function '{}' at {}", call.func, call.span),
+                };
+                diagnostic = diagnostic.with_note(note);
+            }
+            if times > 1 {
+                let note = match period {
+                    1 => format!("the previous call repeats {} more time(s)", times - 1),
+                    _ => format!("the previous {period} calls repeat {} more time(s)", times - 1),
+                };
+                diagnostic = diagnostic.with_note(note);
+            }
+            i += period * times;
+        }
+        diagnostic
+    }
+}
+
+// longest chain of mutually recursive functions that is collapsed in a stack trace
+const MAX_PERIOD: usize = 8;
+
+// shortest sequence of calls at the start that is repeated right after itself, with the
+// number of times it occurs in a row, or the first call occurring once
+fn repetition(calls: &[Call]) -> (usize, usize) {
+    for period in 1..=MAX_PERIOD.min(calls.len() / 2) {
+        let times = calls.chunks_exact(period)
+            .take_while(|chunk| *chunk == &calls[..period])
+            .count();
+        if times > 1 {
+            return (period, times);
+        }
+    }
+    (1, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::Diagnostic;
+    use crate::span::Span;
+
+    use super::{Call, RuntimeError};
+
+    fn call(func: &str, line: usize) -> Call {
+        Call { func: func.to_string(), span: Span::new(0, 1, line, 5) }
+    }
+
+    #[test]
+    fn it_records_the_calls_it_unwinds() {
+        let failure = Span::new(30, 35, 2, 12);
+        let call = Span::new(80, 92, 6, 11);
+        let error = RuntimeError::new(Diagnostic::error("E0505", "attempt to divide 1 by zero".to_string()))
+            .at(failure)
+            .at(Span::new(23, 37, 2, 5))
+            .leave("divide", Span::new(5, 11, 1, 6))
+            .at(call)
+            .leave("main", Span::new(60, 64, 5, 6));
+
+        assert_eq!(error.stack, vec![
+            Call { func: "divide".to_string(), span: failure },
+            Call { func: "main".to_string(), span: call },
+        ]);
+
+        let diagnostic = error.into_diagnostic();
+        assert_eq!(diagnostic.span(), Some(failure));
+        assert_eq!(diagnostic.notes, vec![
+            "in function 'divide' at 2:12".to_string(),
+            "called from function 'main' at 6:11".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn it_collapses_repeated_calls() {
+        let mut error = RuntimeError::new(Diagnostic::error("E0508", "maximum call depth of 100 exceeded".to_string()));
+        error.stack = vec![call("even", 2)];
+        for _ in 0..3 {
+            error.stack.extend([call("odd", 7), call("even", 2)]);
+        }
+        error.stack.push(call("main", 12));
+
+        assert_eq!(error.into_diagnostic().notes, vec![
+            "in function 'even' at 2:5".to_string(),
+            "called from function 'odd' at 7:5".to_string(),
+            "the previous 2 calls repeat 2 more time(s)".to_string(),
+            "called from function 'even' at 2:5".to_string(),
+            "called from function 'main' at 12:5".to_string(),
+        ]);
+    }
+}
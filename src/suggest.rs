@@ -0,0 +1,90 @@
+// finds the name a misspelled identifier was probably meant to be
+
+use crate::token::KEYWORDS;
+
+/// Number of inserted, deleted, replaced or swapped neighbouring chars that turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // distances of the prefixes of a to every prefix of b, for the last two rows and the current one
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// The candidate closest to `name`, if it is close enough to be a typo of it.
+/// Ties go to the alphabetically first candidate so that suggestions are stable.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates.into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The keyword an identifier is probably a typo of. Names shorter than three chars are
+/// one edit away from too many keywords, like `i` from `if`, to suggest anything.
+pub fn keyword(name: &str) -> Option<&'static str> {
+    if name.chars().count() < 3 {
+        return None;
+    }
+    closest(name, KEYWORDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, edit_distance, keyword};
+
+    #[test]
+    fn it_counts_edits() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("count", "count"), 0);
+        assert_eq!(edit_distance("", "let"), 3);
+        assert_eq!(edit_distance("facorial_loop", "factorial_loop"), 1);
+        assert_eq!(edit_distance("sum", "sun"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn it_counts_swapped_chars_as_one_edit() {
+        assert_eq!(edit_distance("retrun", "return"), 1);
+        assert_eq!(edit_distance("fucn", "func"), 1);
+    }
+
+    #[test]
+    fn it_suggests_the_closest_candidate() {
+        let candidates = ["factorial", "factorial_loop", "main"];
+        assert_eq!(closest("facorial_loop", candidates), Some("factorial_loop"));
+        assert_eq!(closest("mian", candidates), Some("main"));
+        assert_eq!(closest("print_all", candidates), None);
+        assert_eq!(closest("main", candidates), None);
+    }
+
+    #[test]
+    fn it_suggests_keywords_for_longer_names_only() {
+        assert_eq!(keyword("retrun"), Some("return"));
+        assert_eq!(keyword("ture"), Some("true"));
+        assert_eq!(keyword("i"), None);
+        assert_eq!(keyword("count"), None);
+    }
+}
@@ -2,6 +2,7 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+use crate::span::Span;
 use crate::value::Value;
 
 #[derive(Debug, Clone)]
@@ -10,11 +11,13 @@ pub struct Symbol {
     pub value: Value,
     pub signature: usize,
     pub is_used: bool,
+    // where the symbol is declared
+    pub span: Span,
 }
 
 impl Symbol {
-    pub fn new(name: String, value: Value, signature: usize) -> Symbol {
-        Symbol { name, value, signature, is_used: false }
+    pub fn new(name: String, value: Value, signature: usize, span: Span) -> Symbol {
+        Symbol { name, value, signature, is_used: false, span }
     }
 }
 
@@ -31,4 +34,45 @@ impl Symbols {
             map: HashMap::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+impl Symbols {
+    // resolves locally first, then through the enclosing tables
+    pub fn is_declared(&self, name: &String) -> bool {
+        self.map.contains_key(name) || match &self.parent {
+            None => false,
+            Some(rc_parent) => rc_parent.borrow().is_declared(name),
+        }
+    }
+
+    pub fn lookup(&self, name: &String) -> Option<Symbol> {
+        match self.map.get(name) {
+            Some(symbol) => Some(symbol.clone()),
+            None => self.parent.as_ref().and_then(|rc_parent| rc_parent.borrow().lookup(name)),
+        }
+    }
+
+    /// Every symbol that can be referenced from this table, inner declarations hide outer ones.
+    pub fn visible(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.map.values().cloned().collect();
+        if let Some(rc_parent) = &self.parent {
+            for symbol in rc_parent.borrow().visible() {
+                if !self.map.contains_key(&symbol.name) {
+                    symbols.push(symbol);
+                }
+            }
+        }
+        symbols
+    }
+
+    pub fn mark_used(&mut self, name: &String) {
+        match self.map.get_mut(name) {
+            Some(symbol) => { symbol.is_used = true; }
+            None => {
+                if let Some(rc_parent) = &self.parent {
+                    rc_parent.borrow_mut().mark_used(name);
+                }
+            }
+        }
+    }
+}
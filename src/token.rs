@@ -21,6 +21,7 @@ pub enum Token {
     COLON,
     SEMICOLON,
     ARROW_R,
+    HASH_SIGN,
 
     // arithmetic ops
     OP_ADD,
@@ -87,6 +88,12 @@ pub enum Token {
     ELSE_NODE,
 }
 
+/// Words the lexer reads as keywords, types or literals instead of identifiers.
+pub const KEYWORDS: [&str; 16] = [
+    "func", "let", "if", "else", "while", "return", "print",
+    "int32", "flt32", "char", "bool", "true", "false", "and", "or", "not",
+];
+
 impl PartialEq for Token {
     fn eq(&self, other: &Self) -> bool {
         discriminant(self) == discriminant(other)
@@ -95,9 +102,56 @@ impl PartialEq for Token {
 
 impl Eq for Token {}
 
+// spelled like in the source, for error messages
 impl Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Token::PARENS_L => write!(f, "("),
+            Token::PARENS_R => write!(f, ")"),
+            Token::BRACKET_L => write!(f, "["),
+            Token::BRACKET_R => write!(f, "]"),
+            Token::BRACE_L => write!(f, "{{"),
+            Token::BRACE_R => write!(f, "}}"),
+            Token::POINT => write!(f, "."),
+            Token::COMMA => write!(f, ","),
+            Token::COLON => write!(f, ":"),
+            Token::SEMICOLON => write!(f, ";"),
+            Token::ARROW_R => write!(f, "->"),
+            Token::HASH_SIGN => write!(f, "#"),
+            Token::OP_ADD => write!(f, "+"),
+            Token::OP_SUB => write!(f, "-"),
+            Token::OP_MUL => write!(f, "*"),
+            Token::OP_DIV => write!(f, "/"),
+            Token::OP_EQ => write!(f, "=="),
+            Token::OP_LT => write!(f, "<"),
+            Token::OP_GT => write!(f, ">"),
+            Token::OP_NEQ => write!(f, "!="),
+            Token::OP_NLT => write!(f, ">="),
+            Token::OP_NGT => write!(f, "<="),
+            Token::OP_NOT => write!(f, "not"),
+            Token::OP_AND => write!(f, "and"),
+            Token::OP_OR => write!(f, "or"),
+            Token::OP_ASSIGN => write!(f, "="),
+            Token::KW_FUNC => write!(f, "func"),
+            Token::KW_LET => write!(f, "let"),
+            Token::KW_IF => write!(f, "if"),
+            Token::KW_ELSE => write!(f, "else"),
+            Token::KW_WHILE => write!(f, "while"),
+            Token::KW_RETURN => write!(f, "return"),
+            Token::KW_PRINT => write!(f, "print"),
+            Token::TYPE_INT32 => write!(f, "int32"),
+            Token::TYPE_FLT32 => write!(f, "flt32"),
+            Token::TYPE_CHAR => write!(f, "char"),
+            Token::TYPE_BOOL => write!(f, "bool"),
+            Token::ID(name) => write!(f, "{}", name),
+            Token::LIT_INT32(i) => write!(f, "{}", i),
+            Token::LIT_FLT32(x) => write!(f, "{:?}", x),
+            Token::LIT_CHAR(c) => write!(f, "'{}'", c),
+            Token::LIT_STRING(s) => write!(f, "\"{}\"", s),
+            Token::LIT_BOOL(b) => write!(f, "{}", b),
+            Token::EOI => write!(f, "end of input"),
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
@@ -1,9 +1,19 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
+use crate::span::Span;
 use crate::symbols::Symbols;
+use crate::types::Type;
 use crate::value::Value;
 
+/// Location of a variable in the stack frames, resolved by the analyzer: the number of
+/// frames to go up from the innermost one, and the index of the variable in that frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct ProgramNode {
     pub symbols: Rc<RefCell<Symbols>>,
@@ -19,21 +29,33 @@ impl ProgramNode {
             func_nodes: vec![],
         }
     }
+
+    /// Deepest nesting of blocks and expressions in any function, each level takes native
+    /// stack in every call of the function.
+    pub fn nesting(&self) -> usize {
+        self.func_nodes.iter().map(|rc_func| rc_func.block_node.nesting()).max().unwrap_or(0)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct FuncNode {
     pub name: String,
     pub parameters: Vec<Parameter>,
+    pub return_type: Option<Type>,
     pub block_node: Rc<BlockNode>,
+    pub span: Span,
+    pub attributes: Vec<Attribute>,
 }
 
 impl FuncNode {
-    pub fn new(name: String, parameters: Vec<Parameter>, block_node: BlockNode) -> FuncNode {
+    pub fn new(name: String, parameters: Vec<Parameter>, return_type: Option<Type>, block_node: BlockNode, span: Span) -> FuncNode {
         FuncNode {
             name,
             parameters,
+            return_type,
             block_node: Rc::new(block_node),
+            span,
+            attributes: vec![],
         }
     }
 
@@ -42,15 +64,37 @@ impl FuncNode {
     }
 }
 
+/// Attribute in front of a function, like `#[allow(unused_variable)]`.
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<(String, Span)>,
+    pub span: Span,
+}
+
+impl Attribute {
+    pub fn new(name: String, args: Vec<(String, Span)>, span: Span) -> Attribute {
+        Attribute {
+            name,
+            args,
+            span,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Parameter {
     pub name: String,
+    pub param_type: Option<Type>,
+    pub span: Span,
 }
 
 impl Parameter {
-    pub fn new(name: String) -> Parameter {
+    pub fn new(name: String, param_type: Option<Type>, span: Span) -> Parameter {
         Parameter {
-            name
+            name,
+            param_type,
+            span,
         }
     }
 }
@@ -59,6 +103,8 @@ impl Parameter {
 pub struct BlockNode {
     pub symbols: Rc<RefCell<Symbols>>,
     pub statements: Vec<Rc<StmtNode>>,
+    // size of the stack frame of the block, set by the analyzer
+    pub slots: Cell<usize>,
 }
 
 impl BlockNode {
@@ -66,8 +112,59 @@ impl BlockNode {
         BlockNode {
             symbols: Rc::new(RefCell::new(Symbols::new(None))),
             statements: vec![],
+            slots: Cell::new(0),
+        }
+    }
+
+    /// Names of all functions called anywhere in the block, including nested blocks.
+    pub fn collect_calls(&self, callees: &mut Vec<String>) {
+        for rc_stmt in &self.statements {
+            match rc_stmt.as_ref() {
+                StmtNode::Let(_) => {}
+                StmtNode::Assign(assignNode) => assignNode.expr.collect_calls(callees),
+                StmtNode::Return(returnNode) => returnNode.expr.collect_calls(callees),
+                StmtNode::Print(printNode) => printNode.expr.collect_calls(callees),
+                StmtNode::While(whileNode) => {
+                    whileNode.condition.collect_calls(callees);
+                    whileNode.body.collect_calls(callees);
+                }
+                StmtNode::IfElse(ifNode) => {
+                    ifNode.condition.collect_calls(callees);
+                    ifNode.ifBody.collect_calls(callees);
+                    if let Some(elseBody) = &ifNode.elseBody {
+                        elseBody.collect_calls(callees);
+                    }
+                }
+            }
         }
     }
+
+    /// Whether the block, including nested blocks, assigns to a variable of the given name.
+    pub fn assigns(&self, name: &str) -> bool {
+        self.statements.iter().any(|rc_stmt| match rc_stmt.as_ref() {
+            StmtNode::Assign(assignNode) => assignNode.name == name,
+            StmtNode::While(whileNode) => whileNode.body.assigns(name),
+            StmtNode::IfElse(ifNode) => {
+                ifNode.ifBody.assigns(name) || ifNode.elseBody.as_ref().is_some_and(|elseBody| elseBody.assigns(name))
+            }
+            StmtNode::Let(_) | StmtNode::Return(_) | StmtNode::Print(_) => false,
+        })
+    }
+
+    /// Deepest nesting of blocks and expressions in the block, including nested blocks.
+    pub fn nesting(&self) -> usize {
+        self.statements.iter().map(|rc_stmt| match rc_stmt.as_ref() {
+            StmtNode::Let(_) => 0,
+            StmtNode::Assign(assignNode) => assignNode.expr.depth(),
+            StmtNode::Return(returnNode) => returnNode.expr.depth(),
+            StmtNode::Print(printNode) => printNode.expr.depth(),
+            StmtNode::While(whileNode) => 1 + whileNode.condition.depth().max(whileNode.body.nesting()),
+            StmtNode::IfElse(ifNode) => {
+                let elseNesting = ifNode.elseBody.as_ref().map_or(0, |elseBody| elseBody.nesting());
+                1 + ifNode.condition.depth().max(ifNode.ifBody.nesting()).max(elseNesting)
+            }
+        }).max().unwrap_or(0)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -80,18 +177,37 @@ pub enum StmtNode {
     IfElse(IfElseNode),
 }
 
+impl StmtNode {
+    pub fn span(&self) -> Span {
+        match self {
+            StmtNode::Let(node) => node.span,
+            StmtNode::Assign(node) => node.span,
+            StmtNode::Return(node) => node.span,
+            StmtNode::Print(node) => node.span,
+            StmtNode::While(node) => node.span,
+            StmtNode::IfElse(node) => node.span,
+        }
+    }
+}
+
 
 #[derive(Debug, Clone)]
 pub struct LetNode {
     pub name: String,
+    pub let_type: Option<Type>,
     pub value: Value,
+    pub span: Span,
+    pub slot: Cell<Option<Slot>>,
 }
 
 impl LetNode {
-    pub fn new(name: String, value: Value) -> LetNode {
+    pub fn new(name: String, let_type: Option<Type>, value: Value, span: Span) -> LetNode {
         LetNode {
             name,
+            let_type,
             value,
+            span,
+            slot: Cell::new(None),
         }
     }
 }
@@ -100,13 +216,17 @@ impl LetNode {
 pub struct AssignNode {
     pub name: String,
     pub expr: Rc<ExprNode>,
+    pub span: Span,
+    pub slot: Cell<Option<Slot>>,
 }
 
 impl AssignNode {
-    pub fn new(name: String, expr: ExprNode) -> AssignNode {
+    pub fn new(name: String, expr: ExprNode, span: Span) -> AssignNode {
         AssignNode {
             name,
             expr: Rc::new(expr),
+            span,
+            slot: Cell::new(None),
         }
     }
 }
@@ -114,12 +234,18 @@ impl AssignNode {
 #[derive(Debug, Clone)]
 pub struct ReturnNode {
     pub expr: Rc<ExprNode>,
+    pub span: Span,
+    // set by the analyzer if the returned value is the result of a call of a script function,
+    // which then replaces the call of the returning function instead of nesting in it
+    pub tail_call: Cell<bool>,
 }
 
 impl ReturnNode {
-    pub fn new(expr: ExprNode) -> ReturnNode {
+    pub fn new(expr: ExprNode, span: Span) -> ReturnNode {
         ReturnNode {
             expr: Rc::new(expr),
+            span,
+            tail_call: Cell::new(false),
         }
     }
 }
@@ -127,12 +253,14 @@ impl ReturnNode {
 #[derive(Debug, Clone)]
 pub struct PrintNode {
     pub expr: Rc<ExprNode>,
+    pub span: Span,
 }
 
 impl PrintNode {
-    pub fn new(expr: ExprNode) -> PrintNode {
+    pub fn new(expr: ExprNode, span: Span) -> PrintNode {
         PrintNode {
             expr: Rc::new(expr),
+            span,
         }
     }
 }
@@ -140,14 +268,19 @@ impl PrintNode {
 #[derive(Debug, Clone)]
 pub struct WhileNode {
     pub condition: Rc<ExprNode>,
+    // source of the condition, literals carry no span of their own
+    pub condition_span: Span,
     pub body: Rc<BlockNode>,
+    pub span: Span,
 }
 
 impl WhileNode {
-    pub fn new(condition: ExprNode, body: BlockNode) -> WhileNode {
+    pub fn new(condition: ExprNode, condition_span: Span, body: BlockNode, span: Span) -> WhileNode {
         WhileNode {
             condition: Rc::new(condition),
+            condition_span,
             body: Rc::new(body),
+            span,
         }
     }
 }
@@ -155,38 +288,97 @@ impl WhileNode {
 #[derive(Debug, Clone)]
 pub struct IfElseNode {
     pub condition: Rc<ExprNode>,
+    // source of the condition, literals carry no span of their own
+    pub condition_span: Span,
     pub ifBody: Rc<BlockNode>,
     pub elseBody: Option<Rc<BlockNode>>,
+    pub span: Span,
 }
 
 impl IfElseNode {
-    pub fn new(condition: ExprNode, ifBody: BlockNode, elseBody: Option<BlockNode>) -> IfElseNode {
+    pub fn new(condition: ExprNode, condition_span: Span, ifBody: BlockNode, elseBody: Option<BlockNode>, span: Span) -> IfElseNode {
         IfElseNode {
             condition: Rc::new(condition),
+            condition_span,
             ifBody: Rc::new(ifBody),
             elseBody: match elseBody {
                 Some(block) => Some(Rc::new(block)),
                 None => None,
             },
+            span,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum ExprNode {
-    Var(String),
+    Var(String, Span, Cell<Option<Slot>>),
     Val(Value),
     String(String),
     Add(Rc<ExprNode>, Rc<ExprNode>),
     Sub(Rc<ExprNode>, Rc<ExprNode>),
     Mul(Rc<ExprNode>, Rc<ExprNode>),
-    Call(String, Vec<Rc<ExprNode>>),
+    Div(Rc<ExprNode>, Rc<ExprNode>),
+    Call(String, Vec<Rc<ExprNode>>, Span, Cell<Option<Slot>>),
     LessThan(Rc<ExprNode>, Rc<ExprNode>),
     GreaterThan(Rc<ExprNode>, Rc<ExprNode>),
     EqualTo(Rc<ExprNode>, Rc<ExprNode>),
     LessThanEq(Rc<ExprNode>, Rc<ExprNode>),
     GreaterThanEq(Rc<ExprNode>, Rc<ExprNode>),
     NotEqualTo(Rc<ExprNode>, Rc<ExprNode>),
+    And(Rc<ExprNode>, Rc<ExprNode>),
+    Or(Rc<ExprNode>, Rc<ExprNode>),
+    Not(Rc<ExprNode>),
 }
 
-impl ExprNode {}
\ No newline at end of file
+impl ExprNode {
+    /// Names of all functions called in the expression, in evaluation order.
+    pub fn collect_calls(&self, callees: &mut Vec<String>) {
+        match self {
+            ExprNode::Var(..) | ExprNode::Val(_) | ExprNode::String(_) => {}
+            ExprNode::Call(name, args, ..) => {
+                callees.push(name.clone());
+                for arg in args {
+                    arg.collect_calls(callees);
+                }
+            }
+            ExprNode::Add(a, b) | ExprNode::Sub(a, b) | ExprNode::Mul(a, b) | ExprNode::Div(a, b)
+            | ExprNode::LessThan(a, b) | ExprNode::GreaterThan(a, b) | ExprNode::EqualTo(a, b)
+            | ExprNode::LessThanEq(a, b) | ExprNode::GreaterThanEq(a, b) | ExprNode::NotEqualTo(a, b)
+            | ExprNode::And(a, b) | ExprNode::Or(a, b) => {
+                a.collect_calls(callees);
+                b.collect_calls(callees);
+            }
+            ExprNode::Not(a) => a.collect_calls(callees),
+        }
+    }
+
+    /// Number of nested operations in the expression, including the arguments of calls.
+    pub fn depth(&self) -> usize {
+        match self {
+            ExprNode::Var(..) | ExprNode::Val(_) | ExprNode::String(_) => 1,
+            ExprNode::Call(_, args, ..) => 1 + args.iter().map(|arg| arg.depth()).max().unwrap_or(0),
+            ExprNode::Add(a, b) | ExprNode::Sub(a, b) | ExprNode::Mul(a, b) | ExprNode::Div(a, b)
+            | ExprNode::LessThan(a, b) | ExprNode::GreaterThan(a, b) | ExprNode::EqualTo(a, b)
+            | ExprNode::LessThanEq(a, b) | ExprNode::GreaterThanEq(a, b) | ExprNode::NotEqualTo(a, b)
+            | ExprNode::And(a, b) | ExprNode::Or(a, b) => 1 + a.depth().max(b.depth()),
+            ExprNode::Not(a) => 1 + a.depth(),
+        }
+    }
+
+    /// Source range covered by the variables and calls of the expression, literals carry no span.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ExprNode::Var(_, span, _) | ExprNode::Call(_, _, span, _) => Some(*span),
+            ExprNode::Val(_) | ExprNode::String(_) => None,
+            ExprNode::Add(a, b) | ExprNode::Sub(a, b) | ExprNode::Mul(a, b) | ExprNode::Div(a, b)
+            | ExprNode::LessThan(a, b) | ExprNode::GreaterThan(a, b) | ExprNode::EqualTo(a, b)
+            | ExprNode::LessThanEq(a, b) | ExprNode::GreaterThanEq(a, b) | ExprNode::NotEqualTo(a, b)
+            | ExprNode::And(a, b) | ExprNode::Or(a, b) => match (a.span(), b.span()) {
+                (Some(span_a), Some(span_b)) => Some(span_a.to(span_b)),
+                (span_a, span_b) => span_a.or(span_b),
+            },
+            ExprNode::Not(a) => a.span(),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::fmt::Display;
+
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Int32,
+    Flt32,
+    Char,
+    Bool,
+    // result of a function without return value
+    Nil,
+    // unannotated code, checked at runtime
+    Dynamic,
+    // type variable of an inferred generic signature
+    Generic(usize),
+}
+
+const CONCRETE_TYPES: [Type; 4] = [Type::Int32, Type::Flt32, Type::Char, Type::Bool];
+
+impl Type {
+    pub fn of(value: &Value) -> Type {
+        match value {
+            Value::Bool(_) => Type::Bool,
+            Value::I32(_) => Type::Int32,
+            Value::F32(_) => Type::Flt32,
+            Value::Chars(_) => Type::Char,
+            Value::Nil | Value::Func(_, _) => Type::Dynamic,
+        }
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        matches!(self, Type::Dynamic | Type::Generic(_))
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int32 | Type::Flt32)
+    }
+
+    /// Whether a value of this type may be the given value, any value for a dynamic type.
+    pub fn holds(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Type::Int32, Value::I32(_)) | (Type::Flt32, Value::F32(_)) | (Type::Char, Value::Chars(_)) => true,
+            (Type::Bool, Value::Bool(_)) | (Type::Nil, Value::Nil) => true,
+            (t, _) => t.is_dynamic(),
+        }
+    }
+
+    pub fn is_compatible(&self, other: &Type) -> bool {
+        self.is_dynamic() || other.is_dynamic() || self == other
+    }
+
+    /// Result type of the binary operator `op`, or `None` if the evaluator rejects the operands.
+    /// A dynamic operand is tried against every concrete type: the operator is only rejected if
+    /// no choice works, and the result stays dynamic if the choices disagree.
+    pub fn binary(op: &str, left: Type, right: Type) -> Option<Type> {
+        let lefts = if left.is_dynamic() { CONCRETE_TYPES.to_vec() } else { vec![left] };
+        let rights = if right.is_dynamic() { CONCRETE_TYPES.to_vec() } else { vec![right] };
+
+        let mut result: Option<Type> = None;
+        for l in &lefts {
+            for r in &rights {
+                if let Some(t) = Self::binary_concrete(op, *l, *r) {
+                    result = match result {
+                        None => Some(t),
+                        Some(prev) if prev == t => Some(t),
+                        Some(_) => Some(Type::Dynamic),
+                    };
+                }
+            }
+        }
+        result
+    }
+
+    /// Every concrete type the other operand of `op` may have when one operand is `known`,
+    /// paired with the result type of that combination.
+    pub fn operand_candidates(op: &str, known: Type, known_is_left: bool) -> Vec<(Type, Type)> {
+        CONCRETE_TYPES.iter()
+            .filter_map(|other| {
+                let result = if known_is_left {
+                    Self::binary_concrete(op, known, *other)
+                } else {
+                    Self::binary_concrete(op, *other, known)
+                };
+                result.map(|result| (*other, result))
+            })
+            .collect()
+    }
+
+    // mirrors Evaluator::arithmetic and Evaluator::relational
+    fn binary_concrete(op: &str, left: Type, right: Type) -> Option<Type> {
+        match op {
+            "+" | "-" | "*" | "/" => {
+                match (left, right) {
+                    (Type::Int32, Type::Int32) => Some(Type::Int32),
+                    (Type::Int32 | Type::Flt32, Type::Int32 | Type::Flt32) => Some(Type::Flt32),
+                    (Type::Char, Type::Bool | Type::Int32 | Type::Flt32 | Type::Char)
+                    | (Type::Bool | Type::Int32 | Type::Flt32, Type::Char) if op == "+" => Some(Type::Char),
+                    _ => None,
+                }
+            }
+            "<" | ">" | "<=" | ">=" => {
+                match (left, right) {
+                    (Type::Int32 | Type::Flt32, Type::Int32 | Type::Flt32) => Some(Type::Bool),
+                    (Type::Char, Type::Char) => Some(Type::Bool),
+                    _ => None,
+                }
+            }
+            "==" | "!=" => Some(Type::Bool),
+            "and" | "or" => {
+                match (left, right) {
+                    (Type::Bool, Type::Bool) => Some(Type::Bool),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int32 => write!(f, "int32"),
+            Type::Flt32 => write!(f, "flt32"),
+            Type::Char => write!(f, "char"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::Dynamic => write!(f, "dynamic"),
+            Type::Generic(n) if *n < 26 => write!(f, "'{}", (b'a' + *n as u8) as char),
+            Type::Generic(n) => write!(f, "'t{}", n),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::rc::Rc;
 use crate::tree::{FuncNode};
+use crate::types::Type;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -13,14 +14,24 @@ pub enum Value {
 }
 
 impl Value {
-    pub fn print(&self) {
+    /// Name of the value's type in runtime errors, values without a static type included.
+    pub fn type_name(&self) -> String {
         match self {
-            Value::Nil => { println!("nil") }
-            Value::Bool(b) => { println!("{}", b) }
-            Value::I32(i) => { println!("{}", i) }
-            Value::F32(f) => { println!("{}", f) }
-            Value::Chars(s) => { println!("{}", s) }
-            Value::Func(func, num_params) => { println!("<func {} {}>", func.name, num_params) }
+            Value::Nil => "nil".to_string(),
+            Value::Func(_, _) => "func".to_string(),
+            _ => Type::of(self).to_string(),
+        }
+    }
+
+    /// The value as print shows it and string concatenation embeds it.
+    pub fn format(&self) -> String {
+        match self {
+            Value::Nil => { "nil".to_string() }
+            Value::Bool(b) => { b.to_string() }
+            Value::I32(i) => { i.to_string() }
+            Value::F32(f) => { f.to_string() }
+            Value::Chars(s) => { s.clone() }
+            Value::Func(func, num_params) => { format!("<func {} {}>", func.name, num_params) }
         }
     }
 }
@@ -34,6 +45,7 @@ impl PartialEq for Value {
             (Value::I32(a), Value::I32(b)) => a == b,
             (Value::F32(a), Value::F32(b)) => a == b,
             (Value::Chars(a), Value::Chars(b)) => a == b,
+            (Value::Func(a, _), Value::Func(b, _)) => Rc::ptr_eq(a, b),
             _ => false
         }
     }
@@ -0,0 +1,359 @@
+use std::iter;
+
+use crate::bytecode::{Bytecode, Op};
+use crate::context::Context;
+use crate::evaluator::Evaluator;
+use crate::executor::Executor;
+use crate::logger::Logger;
+use crate::runtime_error::{RuntimeError, RuntimeResult};
+use crate::value::Value;
+
+// a function running on the virtual machine
+#[derive(Debug, Clone, Copy)]
+struct CallFrame {
+    function: usize,
+    // next instruction, the one before it is running
+    pc: usize,
+    // first local on the stack, the function value called is right below it
+    base: usize,
+}
+
+/// Stack based virtual machine running compiled programs. It gives the same output and errors
+/// as the tree walking `Executor`, which stays the reference for the semantics of a program.
+pub struct Vm {
+    bytecode: Bytecode,
+    context: Context,
+}
+
+impl Vm {
+    pub fn new(bytecode: Bytecode, context: Context) -> Vm {
+        Vm { bytecode, context }
+    }
+
+    /// Runs main, a runtime error stops the program and is returned with the calls it unwound.
+    pub fn execute(&self) -> RuntimeResult<()> {
+        Logger::info("Execute.");
+        self.context.start();
+        Logger::info("Execute Program.");
+
+        let mut globals = self.bytecode.globals.clone();
+        let rc_main = Executor::main_function(&self.bytecode.program, self.global(&globals, "main"))?;
+        let main = self.bytecode.function_index(&rc_main, None);
+        self.run(main, vec![], &mut globals)?;
+
+        Logger::info("Program finished.");
+        Ok(())
+    }
+
+    /// Calls a function of the program with the given arguments and returns its result.
+    pub fn call(&self, name: &str, arguments: Vec<Value>) -> RuntimeResult<Value> {
+        self.context.start();
+        let mut globals = self.bytecode.globals.clone();
+        match self.global(&globals, name) {
+            Value::Func(rc_func, _) => {
+                let function = self.bytecode.function_index(&rc_func, None);
+                self.run(function, arguments, &mut globals)
+            }
+            value => Err(RuntimeError::new(Evaluator::not_a_function(name, &value))),
+        }
+    }
+
+    fn global(&self, globals: &[Value], name: &str) -> Value {
+        Executor::global_index(&self.bytecode.program, name).map_or(Value::Nil, |index| globals[index].clone())
+    }
+
+    // runs a function until it returns, an error leaves every function still running
+    // and is located at the instruction each of them is at
+    fn run(&self, function: usize, arguments: Vec<Value>, globals: &mut [Value]) -> RuntimeResult<Value> {
+        let node = &self.bytecode.functions[function].node;
+        if !self.context.enter() {
+            return Err(Executor::call_depth_exceeded(node, &self.context));
+        }
+        if let Err(error) = Executor::check_arguments(node, &arguments) {
+            self.context.leave();
+            return Err(error);
+        }
+
+        // nil stands in for the function value below the arguments
+        let mut stack = Vec::with_capacity(256);
+        stack.push(Value::Nil);
+        stack.extend(arguments);
+        stack.resize(1 + self.bytecode.functions[function].frame_size, Value::Nil);
+        let mut frame = CallFrame { function, pc: 0, base: 1 };
+        let mut callers = vec![];
+
+        let value = self.dispatch(&mut frame, &mut callers, &mut stack, globals).map_err(|mut error| {
+            for frame in iter::once(&frame).chain(callers.iter().rev()) {
+                let function = &self.bytecode.functions[frame.function];
+                error = error.at(function.spans[frame.pc - 1]).leave(&function.node.name, function.node.span);
+                self.context.leave();
+            }
+            error
+        })?;
+        // the function that returned last, a tail call may have replaced the one called
+        Executor::check_result(&self.bytecode.functions[frame.function].node, value)
+    }
+
+    fn dispatch(&self, frame: &mut CallFrame, callers: &mut Vec<CallFrame>, stack: &mut Vec<Value>, globals: &mut [Value]) -> RuntimeResult<Value> {
+        let mut function = &self.bytecode.functions[frame.function];
+        loop {
+            let op = function.code[frame.pc];
+            frame.pc += 1;
+            match op {
+                Op::Step(count, first) => {
+                    self.context.step_by(count as u64)
+                        .map_err(|(taken, error)| error.at(function.step_spans[first + taken as usize]))?;
+                }
+                Op::Const(index) => stack.push(self.bytecode.constants[index].clone()),
+                Op::Nil => stack.push(Value::Nil),
+                Op::Load(index) => stack.push(stack[frame.base + index].clone()),
+                Op::Store(index) => {
+                    let value = Self::pop(stack);
+                    stack[frame.base + index] = value;
+                }
+                Op::LoadGlobal(index) => stack.push(globals[index].clone()),
+                Op::StoreGlobal(index) => globals[index] = Self::pop(stack),
+                Op::Clear(index, count) => stack[frame.base + index..][..count].fill(Value::Nil),
+                Op::Arithmetic(op) => {
+                    let (value_a, value_b) = Self::pop_operands(stack);
+                    stack.push(Evaluator::arithmetic(value_a, value_b, op, self.context.overflow)?);
+                }
+                Op::Relational(op) => {
+                    let (value_a, value_b) = Self::pop_operands(stack);
+                    stack.push(Evaluator::relational(value_a, value_b, op)?);
+                }
+                Op::Equal => {
+                    let (value_a, value_b) = Self::pop_operands(stack);
+                    stack.push(Value::Bool(Evaluator::equal(&value_a, &value_b)));
+                }
+                Op::NotEqual => {
+                    let (value_a, value_b) = Self::pop_operands(stack);
+                    stack.push(Value::Bool(!Evaluator::equal(&value_a, &value_b)));
+                }
+                Op::Not => match Self::pop(stack) {
+                    Value::Bool(b) => stack.push(Value::Bool(!b)),
+                    value => return Evaluator::logical(value, "not"),
+                },
+                Op::And(target) => match stack.last() {
+                    Some(Value::Bool(false)) => frame.pc = target,
+                    Some(Value::Bool(true)) => { stack.pop(); }
+                    _ => return Evaluator::logical(Self::pop(stack), "and"),
+                },
+                Op::Or(target) => match stack.last() {
+                    Some(Value::Bool(true)) => frame.pc = target,
+                    Some(Value::Bool(false)) => { stack.pop(); }
+                    _ => return Evaluator::logical(Self::pop(stack), "or"),
+                },
+                Op::Logical(op) => {
+                    if !matches!(stack.last(), Some(Value::Bool(_))) {
+                        return Evaluator::logical(Self::pop(stack), op);
+                    }
+                }
+                Op::Jump(target) => frame.pc = target,
+                Op::JumpIfFalse(target, keyword) => match Self::pop(stack) {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => frame.pc = target,
+                    value => return Err(Executor::condition_not_bool(keyword, &value, function.spans[frame.pc - 1])),
+                },
+                Op::Callee(site) => {
+                    if !matches!(stack.last(), Some(Value::Func(_, _))) {
+                        let site = &function.calls[site];
+                        let value = Self::pop(stack);
+                        return Err(RuntimeError::new(Evaluator::not_a_function(&site.name, &value).with_primary(site.span, "called here")));
+                    }
+                }
+                Op::Call(site) => {
+                    let site = &function.calls[site];
+                    let base = stack.len() - site.argc;
+                    let callee = self.callee(&stack[base - 1], site.func);
+                    let node = &self.bytecode.functions[callee].node;
+                    if !self.context.enter() {
+                        return Err(Executor::call_depth_exceeded(node, &self.context));
+                    }
+                    if let Err(error) = Executor::check_arguments(node, &stack[base..]) {
+                        self.context.leave();
+                        return Err(error);
+                    }
+
+                    callers.push(*frame);
+                    *frame = CallFrame { function: callee, pc: 0, base };
+                    function = &self.bytecode.functions[callee];
+                    stack.resize(base + function.frame_size, Value::Nil);
+                }
+                // the callee takes over the frame of the running function
+                Op::TailCall(site) => {
+                    let site = &function.calls[site];
+                    let start = stack.len() - site.argc;
+                    let callee = self.callee(&stack[start - 1], site.func);
+                    Executor::check_arguments(&self.bytecode.functions[callee].node, &stack[start..])?;
+
+                    stack.drain(frame.base - 1..start - 1);
+                    *frame = CallFrame { function: callee, pc: 0, base: frame.base };
+                    function = &self.bytecode.functions[callee];
+                    stack.resize(frame.base + function.frame_size, Value::Nil);
+                }
+                Op::Return => {
+                    let value = Self::pop(stack);
+                    stack.truncate(frame.base - 1);
+                    self.context.leave();
+                    match callers.pop() {
+                        None => return Ok(value),
+                        Some(caller) => {
+                            // like the tree walker, a result of the wrong type is located at the call
+                            let callee = &function.node;
+                            *frame = caller;
+                            function = &self.bytecode.functions[frame.function];
+                            stack.push(Executor::check_result(callee, value)?);
+                        }
+                    }
+                }
+                Op::Print => self.context.print(&Self::pop(stack)),
+            }
+        }
+    }
+
+    // code of the function value checked by the callee instruction
+    fn callee(&self, value: &Value, hint: Option<usize>) -> usize {
+        match value {
+            Value::Func(rc_func, _) => self.bytecode.function_index(rc_func, hint),
+            value => unreachable!("callee checked to be a function, found {value:?}"),
+        }
+    }
+
+    fn pop(stack: &mut Vec<Value>) -> Value {
+        stack.pop().expect("operand on the stack")
+    }
+
+    fn pop_operands(stack: &mut Vec<Value>) -> (Value, Value) {
+        let value_b = Self::pop(stack);
+        let value_a = Self::pop(stack);
+        (value_a, value_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+    use std::thread;
+
+    use crate::analyzer::Analyzer;
+    use crate::bytecode::Compiler;
+    use crate::context::{Context, Output};
+    use crate::diagnostic::Diagnostics;
+    use crate::executor::Executor;
+    use crate::lexer::Lexer;
+    use crate::machine::Backend;
+    use crate::optimizer::Optimizer;
+    use crate::parser::DescentParser;
+    use crate::runtime_error::RuntimeResult;
+    use crate::tree::ProgramNode;
+    use crate::value::Value;
+
+    use super::Vm;
+
+    // the analyzed program, unoptimized and optimized, none if it does not compile
+    fn compile(source: &str) -> Option<[Rc<ProgramNode>; 2]> {
+        let diagnostics = Rc::new(Diagnostics::new("<test>", source));
+        let mut parser = DescentParser::new(Lexer::new(source.to_string()), diagnostics.clone());
+        let program = Rc::new(parser.analyze());
+        if diagnostics.error_count() > 0 {
+            return None;
+        }
+        let analyzer = Analyzer::new(program.clone(), diagnostics.clone());
+        analyzer.analyze().ok()?;
+        let optimized = Optimizer::new(program.clone(), analyzer.into_types(), diagnostics).optimize().ok()?;
+        Some([program, optimized])
+    }
+
+    // the tree walker recurses on the native stack, which is small in tests
+    fn context(fuel: u64) -> Context {
+        let mut context = Context::new();
+        context.fuel = Some(fuel);
+        context.max_call_depth = 50;
+        context
+    }
+
+    fn render(result: RuntimeResult<()>, source: &str) -> Option<String> {
+        result.err().map(|error| error.into_diagnostic().render("<test>", source))
+    }
+
+    // the lines the backend prints when it runs the program and the error it stops with
+    fn execute(program: &Rc<ProgramNode>, backend: Backend, fuel: u64, source: &str) -> (String, Option<String>) {
+        let printed = Rc::new(RefCell::new(String::new()));
+        let mut context = context(fuel);
+        context.output = Output::Capture(printed.clone());
+        let result = match backend {
+            Backend::Tree => Executor::new(program.clone(), context).execute(),
+            Backend::Vm => Vm::new(Compiler::new(program.clone()).compile(), context).execute(),
+        };
+        (printed.take(), render(result, source))
+    }
+
+    #[test]
+    fn it_runs_like_the_tree_walker_on_the_test_files() {
+        // the tree walker recurses natively through the deeply nested test files, more than the
+        // stack of a test thread holds in unoptimized builds
+        let parity = thread::Builder::new().stack_size(64 * 1024 * 1024).spawn(|| {
+            for entry in fs::read_dir("test_files").unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|extension| extension != "fe") {
+                    continue;
+                }
+                let source = fs::read_to_string(&path).unwrap();
+                let Some(programs) = compile(&source) else {
+                    continue;
+                };
+                // fuel ends the endless loop and the benchmarks, where it runs out is compared too
+                for program in programs {
+                    for fuel in [777, 50_000] {
+                        let expected = execute(&program, Backend::Tree, fuel, &source);
+                        let found = execute(&program, Backend::Vm, fuel, &source);
+                        assert_eq!(found, expected, "{} with fuel {fuel}", path.display());
+                    }
+                }
+            }
+        }).unwrap();
+        parity.join().unwrap();
+    }
+
+    #[test]
+    fn it_runs_tail_calls_in_the_frame_of_the_caller() {
+        let source = "
+            func count(n, acc) [
+                if n == 0 [
+                    return acc;
+                ]
+                return count(n - 1, acc + 1);
+            ]
+            func main() [
+            ]
+        ";
+        let [program, _] = compile(source).unwrap();
+        let mut context = Context::new();
+        context.max_call_depth = 1;
+        let vm = Vm::new(Compiler::new(program).compile(), context);
+        assert_eq!(vm.call("count", vec![Value::I32(100_000), Value::I32(0)]).ok(), Some(Value::I32(100_000)));
+    }
+
+    #[test]
+    fn it_rejects_a_while_condition_that_is_not_a_bool_like_the_tree_walker() {
+        let source = "
+            func spin(c) [
+                while c [
+                    c = false;
+                ]
+                return 0;
+            ]
+            func main() [
+            ]
+        ";
+        let [program, _] = compile(source).unwrap();
+        let expected = Executor::new(program.clone(), context(100)).call("spin", vec![Value::I32(1)]);
+        let found = Vm::new(Compiler::new(program).compile(), context(100)).call("spin", vec![Value::I32(1)]);
+        assert_eq!(expected.as_ref().map_err(|error| error.code()).err(), Some("E0504"));
+        let render = |result: RuntimeResult<Value>| render(result.map(|_| ()), source);
+        assert_eq!(render(found), render(expected));
+    }
+}